@@ -14,6 +14,9 @@ contract EscrowSrc {
     uint32 public partsCount;
     uint32 public partsClaimed;
     bool public refunded;
+    // Prefix of every leaf preimage binding the tree to one order, zero for plain leaves;
+    // EscrowDst `get_domain_separator` of the destination escrow
+    bytes32 public domainSeparator;
 
     event SrcCreated(
         address indexed maker,
@@ -48,7 +51,8 @@ contract EscrowSrc {
         uint256 _amount,
         bytes32 _merkleRoot,
         uint32 _partsCount,
-        uint256 _expiryTimestamp
+        uint256 _expiryTimestamp,
+        bytes32 _domainSeparator
     ) external {
         require(maker == address(0), "initialized");
         maker = _maker;
@@ -58,6 +62,7 @@ contract EscrowSrc {
         merkleRoot = _merkleRoot;
         partsCount = _partsCount;
         expiryTimestamp = _expiryTimestamp;
+        domainSeparator = _domainSeparator;
         partsClaimed = 0;
         refunded = false;
 
@@ -78,7 +83,9 @@ contract EscrowSrc {
         require(partIndex == partsClaimed, "parts must be claimed in order");
 
         // Verify Merkle proof
-        bytes32 leaf = keccak256(abi.encodePacked(partIndex, secret));
+        bytes32 leaf = domainSeparator == bytes32(0)
+            ? keccak256(abi.encodePacked(partIndex, secret))
+            : keccak256(abi.encodePacked(domainSeparator, partIndex, secret));
         require(MerkleProof.verify(proof, merkleRoot, leaf), "invalid proof");
 
         // Calculate amount for this part
@@ -143,6 +150,10 @@ contract EscrowSrc {
         return partsClaimed;
     }
 
+    function getDomainSeparator() external view returns (bytes32) {
+        return domainSeparator;
+    }
+
     function getRefunded() external view returns (bool) {
         return refunded;
     }
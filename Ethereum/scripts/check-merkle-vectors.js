@@ -0,0 +1,119 @@
+// scripts/check-merkle-vectors.js
+// Claims every part of EscrowSrc using the shared Merkle test vectors that
+// EscrowDst (ProofMode::SortedPairs) is tested against on the Polkadot side,
+// with plain and with domain-separated leaves.
+const { ethers } = require("hardhat");
+const vectors = require("../../test-vectors/merkle-sorted-pairs.json");
+const domainVectors = require("../../test-vectors/merkle-domain-separated.json");
+
+// keccak256(abi.encodePacked(uint64 chainId, bytes32 factory, bytes32 maker, uint64 nonce)),
+// followed by uint32 fillIndex for the escrow of a partial fill
+function domainSeparator(domain, fillIndex) {
+  const types = ["uint64", "bytes32", "bytes32", "uint64"];
+  const values = [domain.chainId, domain.factory, domain.maker, domain.nonce];
+  if (fillIndex !== undefined) {
+    types.push("uint32");
+    values.push(fillIndex);
+  }
+  return ethers.solidityPackedKeccak256(types, values);
+}
+
+function domainLeaf(separator, index, secret) {
+  return ethers.solidityPackedKeccak256(["bytes32", "uint32", "bytes32"], [separator, index, secret]);
+}
+
+async function deployEscrow(deployer, maker, taker, root, partsCount, separator) {
+  const MockToken = await ethers.getContractFactory("MockERC20");
+  const mockToken = await MockToken.deploy("Test Token", "TEST");
+  await mockToken.waitForDeployment();
+
+  const EscrowSrc = await ethers.getContractFactory("EscrowSrc");
+  const escrow = await EscrowSrc.connect(deployer).deploy();
+  await escrow.waitForDeployment();
+
+  const amount = ethers.parseEther("100");
+  await mockToken.mint(maker.address, amount);
+  await mockToken.connect(maker).approve(await escrow.getAddress(), amount);
+
+  const expiryTimestamp = Math.floor(Date.now() / 1000) + 3600;
+  await escrow.connect(maker).init(
+    maker.address,
+    taker.address,
+    await mockToken.getAddress(),
+    amount,
+    root,
+    partsCount,
+    expiryTimestamp,
+    separator
+  );
+  return escrow;
+}
+
+async function main() {
+  console.log("🚀 Checking shared Merkle test vectors against EscrowSrc...");
+
+  const [deployer, maker, taker] = await ethers.getSigners();
+
+  // Leaves must be keccak256(abi.encodePacked(uint32 partIndex, bytes32 secret))
+  for (const part of vectors.parts) {
+    const leaf = ethers.solidityPackedKeccak256(["uint32", "bytes32"], [part.index, part.secret]);
+    if (leaf !== part.leaf) {
+      throw new Error(`leaf mismatch for part ${part.index}: ${leaf} != ${part.leaf}`);
+    }
+  }
+  console.log("✅ Leaves match abi.encodePacked(partIndex, secret)");
+
+  // Deploy mock ERC20 token and EscrowSrc
+  const escrow = await deployEscrow(deployer, maker, taker, vectors.root, vectors.partsCount, ethers.ZeroHash);
+  console.log("✅ EscrowSrc initialized with root", vectors.root);
+
+  // Claim every part with the shared proofs
+  for (const part of vectors.parts) {
+    await escrow.connect(taker).claimPart(part.proof, part.secret, part.index);
+    console.log(`✅ Part ${part.index} claimed`);
+  }
+
+  // Domain-separated leaves use the destination escrow's separator
+  const separator = domainSeparator(domainVectors.domain);
+  if (separator !== domainVectors.domainSeparator) {
+    throw new Error(`domain separator mismatch: ${separator} != ${domainVectors.domainSeparator}`);
+  }
+  for (const part of domainVectors.parts) {
+    const leaf = domainLeaf(separator, part.index, part.secret);
+    if (leaf !== part.leaf) {
+      throw new Error(`domain-separated leaf mismatch for part ${part.index}: ${leaf} != ${part.leaf}`);
+    }
+  }
+
+  // Fill trees bind leaf i to the escrow of fill i; EscrowDst only
+  for (const fill of domainVectors.fills.leaves) {
+    const fillSeparator = domainSeparator(domainVectors.domain, fill.fillIndex);
+    if (fillSeparator !== fill.domainSeparator) {
+      throw new Error(`separator mismatch for fill ${fill.fillIndex}`);
+    }
+    if (domainLeaf(fillSeparator, fill.fillIndex, fill.secret) !== fill.leaf) {
+      throw new Error(`leaf mismatch for fill ${fill.fillIndex}`);
+    }
+  }
+  console.log("✅ Domain separators and leaves match");
+
+  const boundEscrow = await deployEscrow(
+    deployer,
+    maker,
+    taker,
+    domainVectors.root,
+    domainVectors.partsCount,
+    separator
+  );
+  for (const part of domainVectors.parts) {
+    await boundEscrow.connect(taker).claimPart(part.proof, part.secret, part.index);
+    console.log(`✅ Domain-separated part ${part.index} claimed`);
+  }
+
+  console.log("\n🎉 Shared vectors verified on EscrowSrc");
+}
+
+main().catch((error) => {
+  console.error(error);
+  process.exitCode = 1;
+});
@@ -123,7 +123,8 @@ async function main() {
     escrowAmount,
     merkleRoot,
     partsCount,
-    expiryTimestamp
+    expiryTimestamp,
+    ethers.ZeroHash
   );
   console.log("✅ Escrow initialized");
 
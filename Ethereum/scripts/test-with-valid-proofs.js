@@ -129,7 +129,8 @@ async function main() {
     escrowAmount,
     merkleRoot,
     partsCount,
-    expiryTimestamp
+    expiryTimestamp,
+    ethers.ZeroHash
   );
   console.log("✅ Escrow initialized");
 
@@ -0,0 +1,66 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! `pallet-assets` chain extension and the contract environment exposing it,
+//! shared by the escrow contracts and their test tokens
+
+use ink::env::{DefaultEnvironment, Environment};
+
+/// Identifier of a `pallet-assets` asset
+pub type AssetId = u32;
+
+/// `pallet-assets` transfers the runtime exposes to contracts
+#[ink::chain_extension(extension = 1)]
+pub trait AssetsExtension {
+    type ErrorCode = AssetsError;
+
+    /// Transfer `amount` of `asset_id` from the contract to `to`
+    #[ink(function = 1)]
+    fn transfer(
+        asset_id: AssetId,
+        to: <DefaultEnvironment as Environment>::AccountId,
+        amount: <DefaultEnvironment as Environment>::Balance,
+    );
+
+    /// Transfer `amount` of `asset_id` from `owner` to `to` out of the contract's approval
+    #[ink(function = 2)]
+    fn transfer_approved(
+        asset_id: AssetId,
+        owner: <DefaultEnvironment as Environment>::AccountId,
+        to: <DefaultEnvironment as Environment>::AccountId,
+        amount: <DefaultEnvironment as Environment>::Balance,
+    );
+}
+
+/// Status codes returned by the `pallet-assets` chain extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum AssetsError {
+    /// The runtime rejected the asset operation
+    Failed,
+}
+
+impl ink::env::chain_extension::FromStatusCode for AssetsError {
+    fn from_status_code(status_code: u32) -> Result<(), Self> {
+        match status_code {
+            0 => Ok(()),
+            _ => Err(Self::Failed),
+        }
+    }
+}
+
+/// Default environment extended with `pallet-assets` access
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[ink::scale_derive(TypeInfo)]
+pub enum AssetsEnvironment {}
+
+impl Environment for AssetsEnvironment {
+    const MAX_EVENT_TOPICS: usize = <DefaultEnvironment as Environment>::MAX_EVENT_TOPICS;
+
+    type AccountId = <DefaultEnvironment as Environment>::AccountId;
+    type Balance = <DefaultEnvironment as Environment>::Balance;
+    type Hash = <DefaultEnvironment as Environment>::Hash;
+    type Timestamp = <DefaultEnvironment as Environment>::Timestamp;
+    type BlockNumber = <DefaultEnvironment as Environment>::BlockNumber;
+
+    type ChainExtension = AssetsExtension;
+}
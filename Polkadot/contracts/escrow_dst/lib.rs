@@ -1,28 +1,665 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
-#[ink::contract]
+pub use self::escrow_dst::{
+    fill_secret_index, EscrowDst, EscrowDstRef, EscrowParams, HashAlgorithm, Immutables, LeafDomain, MultiProof, Order,
+    OrderDomain, OrderEncoding, ProofMode, Psp22Error, Stage, TimeUnit, Timelocks,
+};
+
+pub use assets_extension::{AssetId, AssetsEnvironment, AssetsError, AssetsExtension};
+
+/// Environment the escrow runs in
+///
+/// `AssetsEnvironment` with the `pallet-assets` feature. Otherwise the default
+/// environment: code calling a chain extension is rejected on chains that don't
+/// provide one, such as `substrate-contracts-node`.
+#[cfg(feature = "pallet-assets")]
+pub type EscrowEnvironment = AssetsEnvironment;
+#[cfg(not(feature = "pallet-assets"))]
+pub type EscrowEnvironment = ink::env::DefaultEnvironment;
+
+#[ink::contract(env = crate::EscrowEnvironment)]
 mod escrow_dst {
-    use ink::prelude::vec::Vec;
+    use crate::AssetId;
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::prelude::{string::String, vec::Vec};
+    use ink::scale::Encode;
+    use ink::storage::Mapping;
 
     /// Defines the storage of your contract.
+    ///
+    /// With the `immutables-hash` feature only a hash of the creation parameters is
+    /// stored, and messages that need them take the full `Immutables` as their first
+    /// argument, checked against that hash.
     #[ink(storage)]
     pub struct EscrowDst {
+        /// Parameters the escrow was created with
+        #[cfg(not(feature = "immutables-hash"))]
+        immutables: Immutables,
+        /// Hash of the parameters the escrow was created with, see `EscrowParams::hash`
+        #[cfg(feature = "immutables-hash")]
+        immutables_hash: Hash,
+        /// Block timestamp the escrow was instantiated at
+        deployed_at: u64,
+        /// Block number the escrow was instantiated at
+        deployed_at_block: BlockNumber,
+        /// Amount locked through the payable constructor and `fund`
+        funded_amount: Balance,
+        /// Cumulative amount paid out to the maker by claims and fills
+        filled_amount: Balance,
+        /// Part of the safety deposit paid out so far
+        safety_deposit_paid: Balance,
+        /// Claimed parts, part `i` at bit `i % 64` of word `i / 64`
+        claimed_bitmap: Mapping<u32, u64>,
+        /// Secrets revealed by claims and fills, by part or secret index
+        revealed_secrets: Mapping<u32, Hash>,
+        /// Whether the escrow has been refunded
+        refunded: bool,
+    }
+
+    /// How Merkle leaves and nodes are hashed
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum ProofMode {
+        /// Leaf is `hash(secret)` with the escrow's hash algorithm, sibling order follows
+        /// the bits of the part index
+        #[default]
+        Positional,
+        /// Leaf is `hash(abi.encodePacked(uint32 part_index, bytes32 secret))` with the
+        /// escrow's hash algorithm and pairs are sorted before hashing, matching
+        /// `EscrowSrc` and OpenZeppelin's `MerkleProof.verify` under Keccak-256
+        SortedPairs,
+        /// No tree: the root is a plain hashlock `hash(secret)` over a single part, with
+        /// the escrow's hash algorithm
+        Hashlock,
+    }
+
+    /// Hash function used for hashlocks, Merkle leaves and Merkle nodes
+    ///
+    /// Only `Keccak256` matches `EscrowSrc` on Ethereum; the others serve counterparties
+    /// on Bitcoin-like chains and Substrate-native tooling.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum HashAlgorithm {
+        /// Keccak-256, as used by Solidity's `keccak256`
+        #[default]
+        Keccak256,
+        /// SHA-256, as used by Bitcoin HTLCs
+        Sha256,
+        /// Blake2b with a 256-bit output, as used by Substrate
+        Blake2x256,
+    }
+
+    impl HashAlgorithm {
+        /// Hash raw bytes with this algorithm
+        pub fn hash(self, input: &[u8]) -> Hash {
+            use ink::env::hash::{Blake2x256, Keccak256, Sha2x256};
+            let mut output = [0u8; 32];
+            match self {
+                HashAlgorithm::Keccak256 => ink::env::hash_bytes::<Keccak256>(input, &mut output),
+                HashAlgorithm::Sha256 => ink::env::hash_bytes::<Sha2x256>(input, &mut output),
+                HashAlgorithm::Blake2x256 => ink::env::hash_bytes::<Blake2x256>(input, &mut output),
+            }
+            Hash::from(output)
+        }
+    }
+
+    /// Context mixed into every leaf so a secrets tree only unlocks the escrows of one
+    /// order
+    ///
+    /// Together with the escrow's maker, and its fill index for the escrow of a partial
+    /// fill, it forms the domain separator prepended to each leaf preimage, see
+    /// `EscrowParams::domain_separator`. All of it is known before the tree is built,
+    /// unlike the order hash and escrow address, which depend on its root.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct LeafDomain {
+        /// Identifier of the chain the escrow lives on, chosen by the swap protocol
+        pub chain_id: u64,
+        /// Factory deploying the order's escrows
+        pub factory: AccountId,
+        /// Nonce of the order, see `Order::nonce`
+        pub nonce: u64,
+    }
+
+    /// Encoding an `Order` is hashed with
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum OrderEncoding {
+        /// `blake2_256` of the SCALE-encoded domain and order, for Substrate-native tooling
+        #[default]
+        Scale,
+        /// EIP-712 typed data hash `keccak256("\x19\x01" ++ domainSeparator ++ hashStruct(order))`
+        /// over `OrderDomain::EIP712_TYPE` and `Order::EIP712_TYPE`
+        Eip712,
+    }
+
+    /// Domain an order is signed under: the destination chain and the factory that
+    /// deploys its escrows, so a signature is only valid there
+    ///
+    /// The EIP-712 domain is the standard `name`, `version`, `chainId` and `salt` one,
+    /// the factory's 32-byte account standing in the `salt` as it doesn't fit the
+    /// `address verifyingContract`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct OrderDomain {
+        /// Identifier of the factory's chain, chosen by the swap protocol
+        pub chain_id: u64,
+        /// Factory deploying the order's escrows, the EIP-712 `salt`
+        pub factory: AccountId,
+    }
+
+    impl OrderDomain {
+        /// EIP-712 `name` of the domain
+        pub const NAME: &'static str = "Polka-Fusion";
+
+        /// EIP-712 `version` of the domain
+        pub const VERSION: &'static str = "1";
+
+        /// EIP-712 type string of the domain
+        pub const EIP712_TYPE: &'static str =
+            "EIP712Domain(string name,string version,uint256 chainId,bytes32 salt)";
+
+        /// EIP-712 `domainSeparator`
+        pub fn separator(&self) -> Hash {
+            use ink::env::hash::Keccak256;
+            let mut input = Vec::with_capacity(5 * 32);
+            for string in [Self::EIP712_TYPE, Self::NAME, Self::VERSION] {
+                let mut hash = [0u8; 32];
+                ink::env::hash_bytes::<Keccak256>(string.as_bytes(), &mut hash);
+                input.extend_from_slice(&hash);
+            }
+            input.extend_from_slice(&[0u8; 24]);
+            input.extend_from_slice(&self.chain_id.to_be_bytes());
+            input.extend_from_slice(self.factory.as_ref());
+
+            let mut output = [0u8; 32];
+            ink::env::hash_bytes::<Keccak256>(&input, &mut output);
+            Hash::from(output)
+        }
+    }
+
+    /// Cross-chain order both legs of a swap are created from
+    ///
+    /// Its hash identifies the swap on either chain: `EscrowDst` stores it and the
+    /// source escrow is expected to carry the same value. Besides the amounts it fixes
+    /// every parameter of the destination escrow, so a signed order can only be
+    /// deployed the way the maker signed it. Accounts are 32 bytes, an Ethereum
+    /// address is left-padded with zeros.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Order {
+        /// Account that funds the source escrow and receives on the destination chain
+        pub maker: AccountId,
+        /// Resolver that funds the destination escrow
+        pub taker: AccountId,
+        /// Merkle root of the secrets, or the hashlock of a single-part order
+        pub merkle_root: Hash,
+        /// Total number of parts
+        pub parts_count: u32,
+        /// Chain the maker's assets are escrowed on
+        pub src_chain_id: u64,
+        /// Chain the taker's assets are escrowed on
+        pub dst_chain_id: u64,
+        /// Amount the maker locks on the source chain
+        pub making_amount: Balance,
+        /// Amount the taker locks on the destination chain
+        pub taking_amount: Balance,
+        /// PSP22 token the taker locks; `None` locks the native balance
+        pub token: Option<AccountId>,
+        /// `pallet-assets` asset the taker locks; exclusive with `token`
+        pub asset_id: Option<AssetId>,
+        /// Stage offsets of the destination escrow
+        pub timelocks: Timelocks,
+        /// Unit of the `timelocks` offsets
+        pub time_unit: TimeUnit,
+        /// Native deposit the taker posts with the destination escrow
+        pub safety_deposit: Balance,
+        /// How leaves and nodes of the Merkle tree are hashed
+        pub proof_mode: ProofMode,
+        /// Hash function for hashlocks, leaves and nodes
+        pub hash_algorithm: HashAlgorithm,
+        /// Whether the destination escrow binds leaves to the order with a `LeafDomain`
+        pub bind_leaves: bool,
+        /// Maker-chosen value telling otherwise identical orders apart
+        pub nonce: u64,
+    }
+
+    impl Order {
+        /// EIP-712 type string of the order, hashed into `ORDER_TYPEHASH`
+        ///
+        /// Options are a flag followed by the value, zero when absent; enums are their
+        /// variant index.
+        pub const EIP712_TYPE: &'static str = "Order(bytes32 maker,bytes32 taker,bytes32 merkleRoot,\
+            uint32 partsCount,uint64 srcChainId,uint64 dstChainId,uint256 makingAmount,\
+            uint256 takingAmount,bool hasToken,bytes32 token,bool hasAssetId,uint32 assetId,\
+            uint128 timelocks,uint8 timeUnit,uint256 safetyDeposit,uint8 proofMode,\
+            uint8 hashAlgorithm,bool bindLeaves,uint64 nonce)";
+
+        /// Hash identifying the order under `domain` in the given encoding
+        pub fn hash(&self, domain: &OrderDomain, encoding: OrderEncoding) -> Hash {
+            use ink::env::hash::{Blake2x256, Keccak256};
+            let mut output = [0u8; 32];
+            match encoding {
+                OrderEncoding::Scale => {
+                    ink::env::hash_encoded::<Blake2x256, _>(&(domain, self), &mut output)
+                }
+                OrderEncoding::Eip712 => {
+                    let mut input = Vec::with_capacity(66);
+                    input.extend_from_slice(b"\x19\x01");
+                    input.extend_from_slice(domain.separator().as_ref());
+                    input.extend_from_slice(self.struct_hash().as_ref());
+                    ink::env::hash_bytes::<Keccak256>(&input, &mut output)
+                }
+            }
+            Hash::from(output)
+        }
+
+        /// EIP-712 `hashStruct` of the order
+        pub fn struct_hash(&self) -> Hash {
+            use ink::env::hash::Keccak256;
+            let mut input = Vec::with_capacity(20 * 32);
+            let mut type_hash = [0u8; 32];
+            ink::env::hash_bytes::<Keccak256>(Self::EIP712_TYPE.as_bytes(), &mut type_hash);
+            input.extend_from_slice(&type_hash);
+            input.extend_from_slice(self.maker.as_ref());
+            input.extend_from_slice(self.taker.as_ref());
+            input.extend_from_slice(self.merkle_root.as_ref());
+
+            // Integers are big-endian, left-padded to 32 bytes
+            let push_uint = |input: &mut Vec<u8>, value: u128| {
+                input.extend_from_slice(&[0u8; 16]);
+                input.extend_from_slice(&value.to_be_bytes());
+            };
+            for value in [
+                u128::from(self.parts_count),
+                u128::from(self.src_chain_id),
+                u128::from(self.dst_chain_id),
+                self.making_amount,
+                self.taking_amount,
+                u128::from(self.token.is_some()),
+            ] {
+                push_uint(&mut input, value);
+            }
+            input.extend_from_slice(self.token.as_ref().map_or(&[0u8; 32], |token| token.as_ref()));
+            for value in [
+                u128::from(self.asset_id.is_some()),
+                u128::from(self.asset_id.unwrap_or_default()),
+                self.timelocks.0,
+                self.time_unit as u128,
+                self.safety_deposit,
+                self.proof_mode as u128,
+                self.hash_algorithm as u128,
+                u128::from(self.bind_leaves),
+                u128::from(self.nonce),
+            ] {
+                push_uint(&mut input, value);
+            }
+
+            let mut output = [0u8; 32];
+            ink::env::hash_bytes::<Keccak256>(&input, &mut output);
+            Hash::from(output)
+        }
+    }
+
+    /// Index of the secret a fill of `amount` reveals after `filled` of `total` was filled,
+    /// with N = `parts_count` parts and N+1 secrets
+    ///
+    /// Fusion+'s rule: the bucket the cumulative fill ends in,
+    /// `(filled + amount - 1) * N / total`, or the extra secret N for the fill completing
+    /// the order. `None` if `amount` is zero, overfills `total`, or stays in the bucket of
+    /// the previous fill. `EscrowDst::claim_fill` applies it to one escrow's funding, the
+    /// factory to the fills of one order.
+    pub fn fill_secret_index(
+        total: Balance,
+        parts_count: u32,
+        filled: Balance,
+        amount: Balance,
+    ) -> Option<u32> {
+        let new_filled = filled
+            .checked_add(amount)
+            .filter(|new_filled| amount > 0 && *new_filled <= total)?;
+
+        // Bucket the cumulative fill ends in
+        let bucket = |filled: Balance| {
+            filled
+                .saturating_sub(1)
+                .checked_mul(Balance::from(parts_count))
+                .and_then(|scaled| scaled.checked_div(total))
+        };
+        if new_filled == total {
+            // Only the extra secret completes the order
+            return Some(parts_count);
+        }
+        let index = bucket(new_filled)?;
+        if filled > 0 && index == bucket(filled)? {
+            // Fill stays within the bucket of the previous one
+            return None;
+        }
+        u32::try_from(index).ok()
+    }
+
+    /// Merkle multiproof for several leaves, in OpenZeppelin's `multiProofVerify` format
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct MultiProof {
+        /// Sibling hashes not derivable from the leaves, in consumption order
+        pub proof: Vec<Hash>,
+        /// For each hashing step, whether the second operand comes from the leaves and
+        /// computed hashes (`true`) or from `proof` (`false`)
+        pub proof_flags: Vec<bool>,
+    }
+
+    /// Lifecycle stages of the escrow, in the order they are entered
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Stage {
+        /// Waiting for source chain finality, nothing can be claimed or refunded
+        FinalityLock,
+        /// Only the taker may claim parts
+        ExclusiveWithdrawal,
+        /// Anyone may claim parts on behalf of the maker
+        PublicWithdrawal,
+        /// Only the taker may refund
+        ExclusiveCancellation,
+        /// Anyone may trigger the refund to the taker
+        PublicCancellation,
+    }
+
+    /// Start offsets of the stages after `FinalityLock`, packed like Fusion+'s
+    /// `Timelocks`: 32 bits per stage, lowest bits first, in the escrow's `TimeUnit`
+    /// (milliseconds, seconds or blocks) relative to its deployment
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct Timelocks(u128);
+
+    /// Unit of the timelock offsets
+    ///
+    /// ink!'s `block_timestamp()` is in milliseconds while Solidity's `block.timestamp`
+    /// is in seconds, so offsets copied from `EscrowSrc` should use `Seconds`. `Blocks`
+    /// measures stages in `block_number()` instead, for deadlines that must not
+    /// depend on collator-set timestamps.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum TimeUnit {
+        /// Milliseconds, the unit of `block_timestamp()`
+        #[default]
+        Milliseconds,
+        /// Seconds, the unit of Ethereum's `block.timestamp`
+        Seconds,
+        /// Blocks, counted with `block_number()`
+        Blocks,
+    }
+
+    impl TimeUnit {
+        /// Whether offsets are measured in blocks rather than block timestamps
+        pub const fn is_block_number(self) -> bool {
+            matches!(self, TimeUnit::Blocks)
+        }
+
+        /// Convert `value` in this unit to the unit of its clock: milliseconds of
+        /// `block_timestamp()`, or blocks of `block_number()`
+        pub const fn to_clock(self, value: u64) -> u64 {
+            match self {
+                TimeUnit::Milliseconds | TimeUnit::Blocks => value,
+                TimeUnit::Seconds => value.saturating_mul(1000),
+            }
+        }
+    }
+
+    impl Timelocks {
+        /// Longest offset accepted at construction, 90 days in milliseconds
+        pub const MAX_MILLIS: u64 = 90 * 24 * 60 * 60 * 1000;
+
+        /// Longest offset accepted for `TimeUnit::Blocks`, 90 days of 6 second blocks
+        pub const MAX_BLOCKS: u64 = Self::MAX_MILLIS / 6000;
+
+        /// Pack the start offsets of each stage
+        pub const fn new(
+            withdrawal: u32,
+            public_withdrawal: u32,
+            cancellation: u32,
+            public_cancellation: u32,
+        ) -> Self {
+            Self(
+                withdrawal as u128
+                    | (public_withdrawal as u128) << 32
+                    | (cancellation as u128) << 64
+                    | (public_cancellation as u128) << 96,
+            )
+        }
+
+        /// Offset from deployment at which `stage` starts
+        pub const fn get(self, stage: Stage) -> u32 {
+            match stage {
+                Stage::FinalityLock => 0,
+                _ => (self.0 >> ((stage as u32 - 1) * 32)) as u32,
+            }
+        }
+
+        /// Offset at which the exclusive withdrawal starts, ending the finality lock
+        pub const fn withdrawal(self) -> u32 {
+            self.get(Stage::ExclusiveWithdrawal)
+        }
+
+        /// Offset at which anyone may start claiming parts
+        pub const fn public_withdrawal(self) -> u32 {
+            self.get(Stage::PublicWithdrawal)
+        }
+
+        /// Offset at which claims stop and the taker may refund
+        pub const fn cancellation(self) -> u32 {
+            self.get(Stage::ExclusiveCancellation)
+        }
+
+        /// Offset at which anyone may trigger the refund
+        pub const fn public_cancellation(self) -> u32 {
+            self.get(Stage::PublicCancellation)
+        }
+
+        /// Whether the stages start in order
+        pub const fn is_ordered(self) -> bool {
+            self.withdrawal() <= self.public_withdrawal()
+                && self.public_withdrawal() <= self.cancellation()
+                && self.cancellation() <= self.public_cancellation()
+        }
+
+        /// Offset from deployment at which `stage` starts, in the unit of its clock
+        pub const fn get_on_clock(self, stage: Stage, unit: TimeUnit) -> u64 {
+            unit.to_clock(self.get(stage) as u64)
+        }
+
+        /// Whether cancellation lies in the future and no stage starts after
+        /// `MAX_MILLIS` (`MAX_BLOCKS`), which catches absolute timestamps and unit mix-ups
+        pub const fn is_within_bounds(self, unit: TimeUnit) -> bool {
+            let max = if unit.is_block_number() {
+                Self::MAX_BLOCKS
+            } else {
+                Self::MAX_MILLIS
+            };
+            self.cancellation() > 0 && self.get_on_clock(Stage::PublicCancellation, unit) <= max
+        }
+
+        /// Stage an escrow deployed at `deployed_at` is in at `now`, both read from
+        /// the clock of `unit`
+        pub fn stage_at(self, deployed_at: u64, now: u64, unit: TimeUnit) -> Stage {
+            let elapsed = now.saturating_sub(deployed_at);
+            [
+                Stage::PublicCancellation,
+                Stage::ExclusiveCancellation,
+                Stage::PublicWithdrawal,
+                Stage::ExclusiveWithdrawal,
+            ]
+            .into_iter()
+            .find(|stage| elapsed >= self.get_on_clock(*stage, unit))
+            .unwrap_or(Stage::FinalityLock)
+        }
+    }
+
+    /// Parameters an escrow is created with
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct EscrowParams {
         /// Maker's AccountId
-        maker: AccountId,
+        pub maker: AccountId,
         /// Taker's AccountId
-        taker: AccountId,
+        pub taker: AccountId,
         /// Merkle root of N+1 secrets
-        merkle_root: Hash,
+        pub merkle_root: Hash,
         /// Total number of parts
-        parts_count: u32,
-        /// Expiry timestamp
-        expiry_timestamp: u64,
-        /// Number of parts claimed so far
-        parts_claimed: u32,
-        /// Whether the escrow has been refunded
-        refunded: bool,
+        pub parts_count: u32,
+        /// Stage offsets relative to the escrow's deployment
+        pub timelocks: Timelocks,
+        /// Unit of the `timelocks` offsets
+        pub time_unit: TimeUnit,
+        /// PSP22 token to escrow instead of the native balance; `None` escrows native
+        pub token: Option<AccountId>,
+        /// `pallet-assets` asset to escrow instead of the native balance; exclusive
+        /// with `token`, and only with the `pallet-assets` feature
+        pub asset_id: Option<AssetId>,
+        /// Amount the escrow must hold before parts can be claimed
+        pub amount: Balance,
+        /// Native deposit the taker posts at creation, on top of `amount`
+        pub safety_deposit: Balance,
+        /// How leaves and nodes of the Merkle tree are hashed
+        pub proof_mode: ProofMode,
+        /// Hash function for hashlocks, leaves and nodes
+        pub hash_algorithm: HashAlgorithm,
+        /// Bind leaves to the order; `None` keeps leaves reusable across orders
+        pub leaf_domain: Option<LeafDomain>,
+        /// Hash of the order the escrow settles, see `Order::hash`
+        pub order_hash: Hash,
+        /// Secret index of the partial fill the escrow locks, which `claim_fill` must
+        /// reveal; `None` for an escrow of the whole order
+        pub fill_index: Option<u32>,
+    }
+
+    /// Parameters fixed for the lifetime of an escrow, passed back to its messages in
+    /// `immutables-hash` builds
+    pub type Immutables = EscrowParams;
+
+    impl EscrowParams {
+        /// `blake2_256` of the SCALE-encoded parameters, what `immutables-hash` builds store
+        pub fn hash(&self) -> Hash {
+            let mut output = [0u8; 32];
+            ink::env::hash_encoded::<ink::env::hash::Blake2x256, _>(self, &mut output);
+            Hash::from(output)
+        }
+
+        /// Leaf for a part or secret index according to the proof mode, prefixed with
+        /// the domain separator if there is one
+        pub fn leaf(&self, secret: Hash, index: u32) -> Hash {
+            let mut input = Vec::new();
+            if let Some(domain_separator) = self.domain_separator() {
+                input.extend_from_slice(domain_separator.as_ref());
+            }
+            if self.proof_mode == ProofMode::SortedPairs {
+                // abi.encodePacked(uint32, bytes32): big-endian index followed by the secret
+                input.extend_from_slice(&index.to_be_bytes());
+            }
+            input.extend_from_slice(secret.as_ref());
+            self.hash_algorithm.hash(&input)
+        }
+
+        /// Hash `abi.encodePacked(uint64 chain_id, bytes32 factory, bytes32 maker,
+        /// uint64 nonce)`, followed by `uint32 fill_index` for the escrow of a partial
+        /// fill, if the leaves are domain-separated
+        ///
+        /// The fill index identifies the escrow among the order's: a tree for partial
+        /// fills binds leaf `i` to the escrow of fill `i` only, and a whole-order tree
+        /// to the whole-order escrow.
+        pub fn domain_separator(&self) -> Option<Hash> {
+            let domain = self.leaf_domain?;
+            let mut input = Vec::new();
+            input.extend_from_slice(&domain.chain_id.to_be_bytes());
+            input.extend_from_slice(domain.factory.as_ref());
+            input.extend_from_slice(self.maker.as_ref());
+            input.extend_from_slice(&domain.nonce.to_be_bytes());
+            if let Some(fill_index) = self.fill_index {
+                input.extend_from_slice(&fill_index.to_be_bytes());
+            }
+            Some(self.hash_algorithm.hash(&input))
+        }
+    }
+
+    /// Errors defined by the PSP22 standard, decoded from token calls
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Psp22Error {
+        /// Custom error type for implementation-based errors
+        Custom(String),
+        /// Returned when an account does not have enough tokens
+        InsufficientBalance,
+        /// Returned when the allowance is not sufficient
+        InsufficientAllowance,
+        /// Returned when the recipient is the zero address
+        ZeroRecipientAddress,
+        /// Returned when the sender is the zero address
+        ZeroSenderAddress,
+        /// Returned when a safe transfer check fails
+        SafeTransferCheckFailed(String),
     }
 
+    /// Errors that can occur when interacting with the escrow
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        /// Escrow has already been refunded
+        Refunded,
+        /// Escrow has expired
+        Expired,
+        /// Escrow has not expired yet
+        NotExpired,
+        /// Escrow is still in its finality lock
+        FinalityLock,
+        /// Part index is out of range
+        InvalidPartIndex,
+        /// Part has already been claimed
+        AlreadyClaimed,
+        /// Merkle proof does not match the root
+        InvalidProof,
+        /// Parts count is zero, or not one for a single-hashlock escrow
+        InvalidPartsCount,
+        /// Escrow has not been funded to the expected amount yet
+        NotFunded,
+        /// Escrow is already fully funded
+        AlreadyFunded,
+        /// Funding is zero or exceeds the expected amount
+        InvalidFundingAmount,
+        /// Fill amount is zero, exceeds the unfilled amount, or reaches no new secret
+        InvalidFillAmount,
+        /// Secret index does not match the cumulative fill or the escrow's fill index
+        InvalidSecretIndex,
+        /// The call is not available in the escrow's proof mode, or parts are claimed
+        /// from the escrow of a partial fill
+        UnsupportedProofMode,
+        /// Value transferred at creation does not cover the safety deposit
+        InsufficientSafetyDeposit,
+        /// Funding does not match the escrowed asset, or the escrow is for a
+        /// `pallet-assets` asset without the `pallet-assets` feature
+        AssetMismatch,
+        /// Caller is not the taker and the current stage is not public
+        NotTaker,
+        /// Timelock stages do not start in order
+        InvalidTimelocks,
+        /// Cancellation starts at deployment or a stage starts after `Timelocks::MAX_MILLIS`
+        /// (`Timelocks::MAX_BLOCKS` for block numbers)
+        TimelocksOutOfBounds,
+        /// Native balance transfer failed
+        TransferFailed,
+        /// Immutables passed in don't hash to the stored immutables hash
+        InvalidImmutables,
+    }
+
+    /// Result type for escrow operations
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    #[cfg(not(feature = "immutables-hash"))]
     #[ink(event)]
     pub struct DstCreated {
         #[ink(topic)]
@@ -30,9 +667,34 @@ mod escrow_dst {
         #[ink(topic)]
         taker: AccountId,
         #[ink(topic)]
+        order_hash: Hash,
         merkle_root: Hash,
         parts_count: u32,
-        expiry_timestamp: u64,
+        timelocks: Timelocks,
+        deployed_at: u64,
+    }
+
+    /// `DstCreated` of the `immutables-hash` build, carrying the full immutables the
+    /// escrow only stores the hash of, as its messages take them
+    #[cfg(feature = "immutables-hash")]
+    #[ink(event)]
+    pub struct DstCreated {
+        #[ink(topic)]
+        maker: AccountId,
+        #[ink(topic)]
+        taker: AccountId,
+        #[ink(topic)]
+        order_hash: Hash,
+        deployed_at: u64,
+        immutables: Immutables,
+    }
+
+    #[ink(event)]
+    pub struct Funded {
+        #[ink(topic)]
+        from: AccountId,
+        amount: Balance,
+        funded_amount: Balance,
     }
 
     #[ink(event)]
@@ -53,384 +715,3339 @@ mod escrow_dst {
         amount: Balance,
     }
 
+    #[ink(event)]
+    pub struct SafetyDepositPaid {
+        #[ink(topic)]
+        to: AccountId,
+        amount: Balance,
+    }
+
     impl EscrowDst {
         /// Constructor that initializes the escrow with the given parameters
-        #[ink(constructor)]
-        pub fn new(
-            maker: AccountId,
-            taker: AccountId,
-            merkle_root: Hash,
-            parts_count: u32,
-            expiry_timestamp: u64,
-        ) -> Self {
-            let instance = Self {
-                maker,
-                taker,
-                merkle_root,
-                parts_count,
-                expiry_timestamp,
-                parts_claimed: 0,
+        ///
+        /// The transferred value must cover `safety_deposit`; anything above it is
+        /// locked as (part of) the expected `amount`. Token escrows only take the
+        /// safety deposit here and are funded through `fund_token`, as are
+        /// `pallet-assets` escrows.
+        #[ink(constructor, payable)]
+        pub fn new(params: EscrowParams) -> Result<Self> {
+            // At most one non-native asset can be escrowed
+            if params.token.is_some() && params.asset_id.is_some() {
+                return Err(Error::AssetMismatch);
+            }
+
+            // `pallet-assets` transfers need the chain extension
+            #[cfg(not(feature = "pallet-assets"))]
+            if params.asset_id.is_some() {
+                return Err(Error::AssetMismatch);
+            }
+
+            // Stages must follow each other
+            if !params.timelocks.is_ordered() {
+                return Err(Error::InvalidTimelocks);
+            }
+
+            // Expiry must be in the future and plausible in the chosen unit
+            if !params.timelocks.is_within_bounds(params.time_unit) {
+                return Err(Error::TimelocksOutOfBounds);
+            }
+
+            // A hashlock unlocks exactly one part
+            if params.proof_mode == ProofMode::Hashlock && params.parts_count != 1 {
+                return Err(Error::InvalidPartsCount);
+            }
+
+            // A partial fill reveals one of the N+1 secrets, which a hashlock doesn't have
+            if let Some(fill_index) = params.fill_index {
+                if params.proof_mode == ProofMode::Hashlock {
+                    return Err(Error::UnsupportedProofMode);
+                }
+                if fill_index > params.parts_count {
+                    return Err(Error::InvalidSecretIndex);
+                }
+            }
+            let deployed_at = Self::env().block_timestamp();
+            let deployed_at_block = Self::env().block_number();
+
+            let mut instance = Self {
+                #[cfg(not(feature = "immutables-hash"))]
+                immutables: params.clone(),
+                #[cfg(feature = "immutables-hash")]
+                immutables_hash: params.hash(),
+                deployed_at,
+                deployed_at_block,
+                funded_amount: 0,
+                filled_amount: 0,
+                safety_deposit_paid: 0,
+                claimed_bitmap: Mapping::default(),
+                revealed_secrets: Mapping::default(),
                 refunded: false,
             };
 
             // Emit DstCreated event
+            #[cfg(not(feature = "immutables-hash"))]
             instance.env().emit_event(DstCreated {
-                maker,
-                taker,
-                merkle_root,
-                parts_count,
-                expiry_timestamp,
+                maker: params.maker,
+                taker: params.taker,
+                order_hash: params.order_hash,
+                merkle_root: params.merkle_root,
+                parts_count: params.parts_count,
+                timelocks: params.timelocks,
+                deployed_at,
             });
+            #[cfg(feature = "immutables-hash")]
+            instance.env().emit_event(DstCreated {
+                maker: params.maker,
+                taker: params.taker,
+                order_hash: params.order_hash,
+                deployed_at,
+                immutables: params.clone(),
+            });
+
+            // Keep the safety deposit aside and lock the rest of the transferred value
+            let value = instance
+                .env()
+                .transferred_value()
+                .checked_sub(params.safety_deposit)
+                .ok_or(Error::InsufficientSafetyDeposit)?;
+            if value > 0 {
+                if !Self::is_native(&params) {
+                    return Err(Error::AssetMismatch);
+                }
+                instance.lock_funds(&params, value)?;
+            }
 
-            instance
+            Ok(instance)
         }
 
-        /// Claim a part using Merkle proof and secret
+        /// Get the block timestamp the escrow was instantiated at
         #[ink(message)]
-        pub fn claim_part(
+        pub fn get_deployed_at(&self) -> u64 {
+            self.deployed_at
+        }
+
+        /// Get the deployment timestamp in seconds, as Ethereum's `block.timestamp`
+        #[ink(message)]
+        pub fn get_deployed_at_seconds(&self) -> u64 {
+            self.deployed_at / 1000
+        }
+
+        /// Get the block number the escrow was deployed at
+        #[ink(message)]
+        pub fn get_deployed_at_block(&self) -> BlockNumber {
+            self.deployed_at_block
+        }
+
+        /// Get the amount locked so far
+        #[ink(message)]
+        pub fn get_funded_amount(&self) -> Balance {
+            self.funded_amount
+        }
+
+        /// Get the cumulative amount paid out to the maker
+        #[ink(message)]
+        pub fn get_filled_amount(&self) -> Balance {
+            self.filled_amount
+        }
+
+        /// Whether the part at `part_index` has been claimed
+        #[ink(message)]
+        pub fn is_part_claimed(&self, part_index: u32) -> bool {
+            let word = self.claimed_bitmap.get(part_index / 64).unwrap_or_default();
+            word & 1 << (part_index % 64) != 0
+        }
+
+        /// Get the secret revealed for the part or fill secret at `index`
+        #[ink(message)]
+        pub fn get_revealed_secret(&self, index: u32) -> Option<Hash> {
+            self.revealed_secrets.get(index)
+        }
+
+        /// Get refunded status
+        #[ink(message)]
+        pub fn get_refunded(&self) -> bool {
+            self.refunded
+        }
+
+        /// Get current native balance
+        #[ink(message)]
+        pub fn get_balance(&self) -> Balance {
+            self.env().balance()
+        }
+
+        /// Lock native balance into the escrow until the expected amount is reached
+        fn fund_with(&mut self, immutables: &Immutables) -> Result<()> {
+            // Check if escrow is not refunded
+            if self.refunded {
+                return Err(Error::Refunded);
+            }
+
+            // Check if escrow still needs funding
+            if self.is_funded_with(immutables) {
+                return Err(Error::AlreadyFunded);
+            }
+
+            // Token and asset escrows are funded through `fund_token`
+            if !Self::is_native(immutables) {
+                return Err(Error::AssetMismatch);
+            }
+
+            self.lock_funds(immutables, self.env().transferred_value())
+        }
+
+        /// Pull `value` PSP22 tokens or `pallet-assets` units from the caller until
+        /// the expected amount is reached
+        fn fund_token_with(&mut self, immutables: &Immutables, value: Balance) -> Result<()> {
+            // Check if escrow is not refunded
+            if self.refunded {
+                return Err(Error::Refunded);
+            }
+
+            // Check if escrow still needs funding
+            if self.is_funded_with(immutables) {
+                return Err(Error::AlreadyFunded);
+            }
+
+            // Native escrows are funded through `fund`
+            if Self::is_native(immutables) {
+                return Err(Error::AssetMismatch);
+            }
+
+            // Validate the amount before pulling tokens; a failed pull reverts it
+            self.lock_funds(immutables, value)?;
+
+            let caller = self.env().caller();
+            let escrow = self.env().account_id();
+            #[cfg(feature = "pallet-assets")]
+            if let Some(asset_id) = immutables.asset_id {
+                return self
+                    .env()
+                    .extension()
+                    .transfer_approved(asset_id, caller, escrow, value)
+                    .map_err(|_| Error::TransferFailed);
+            }
+            let Some(token) = immutables.token else {
+                return Err(Error::AssetMismatch);
+            };
+
+            Self::call_psp22(token, Self::psp22_transfer_from(caller, escrow, value))
+        }
+
+        /// Claim a part using Merkle proof and secret
+        fn claim_part_with(
             &mut self,
+            immutables: &Immutables,
             proof: Vec<Hash>,
             secret: Hash,
             part_index: u32,
-        ) {
-            // Check if escrow is not refunded
-            if self.refunded {
-                ink::env::debug_println!("Escrow already refunded");
-                return;
-            }
+        ) -> Result<()> {
+            self.ensure_withdrawal_open(immutables)?;
 
-            // Check if not expired
-            if self.env().block_timestamp() >= self.expiry_timestamp {
-                ink::env::debug_println!("Escrow expired");
-                return;
+            // A partial fill's escrow only releases its own secret, through `claim_fill`
+            if immutables.fill_index.is_some() {
+                return Err(Error::UnsupportedProofMode);
             }
 
             // Check if part index is valid
-            if part_index >= self.parts_count {
-                ink::env::debug_println!("Invalid part index");
-                return;
+            if part_index >= immutables.parts_count {
+                return Err(Error::InvalidPartIndex);
             }
 
             // Check if part is already claimed
-            if part_index < self.parts_claimed {
-                ink::env::debug_println!("Part already claimed");
-                return;
+            if self.is_part_claimed(part_index) {
+                return Err(Error::AlreadyClaimed);
             }
 
-            // Verify Merkle proof
-            if !self.verify_merkle_proof(proof, secret, part_index) {
-                ink::env::debug_println!("Invalid Merkle proof");
-                return;
+            // Check if escrow is fully funded
+            if !self.is_funded_with(immutables) {
+                return Err(Error::NotFunded);
             }
 
-            // Calculate amount for this part
-            let total_balance = self.env().balance();
-            let parts_count_u128 = self.parts_count as u128;
-            
-            // Safe division with explicit check
-            let amount_per_part = if parts_count_u128 == 0 {
-                ink::env::debug_println!("Invalid parts count");
-                return;
-            } else {
-                // Use checked_div to avoid clippy warning
-                match total_balance.checked_div(parts_count_u128) {
-                    Some(amount) => amount,
-                    None => {
-                        ink::env::debug_println!("Division failed");
-                        return;
-                    }
-                }
-            };
+            // Verify Merkle proof
+            if !self.verify_merkle_proof(immutables, proof, secret, part_index) {
+                return Err(Error::InvalidProof);
+            }
 
-            let amount = if part_index == self.parts_count.saturating_sub(1) {
-                // Last part gets any remainder
-                let claimed_parts = self.parts_count.saturating_sub(1) as u128;
-                total_balance.saturating_sub(amount_per_part.saturating_mul(claimed_parts))
-            } else {
-                amount_per_part
-            };
+            // Calculate amount for this part, which fills may already have paid out
+            let amount = self.part_amount(immutables, part_index)?;
+            if amount > self.funded_amount.saturating_sub(self.filled_amount) {
+                return Err(Error::InvalidFillAmount);
+            }
 
             // Transfer amount to maker
-            if amount > 0 && self.env().transfer(self.maker, amount).is_err() {
-                ink::env::debug_println!("Transfer failed");
-                return;
+            if amount > 0 {
+                self.transfer_asset(immutables, immutables.maker, amount)?;
             }
 
-            // Update parts claimed
-            self.parts_claimed = part_index.saturating_add(1);
+            // Reward the caller with this part's share of the safety deposit
+            let deposit = Self::split(immutables.safety_deposit, immutables.parts_count, part_index)?;
+            self.pay_safety_deposit(immutables, deposit)?;
+
+            // Mark the part claimed
+            self.mark_claimed(part_index, secret);
+            self.filled_amount = self.filled_amount.saturating_add(amount);
 
             // Emit PartClaimed event
             self.env().emit_event(PartClaimed {
-                maker: self.maker,
-                taker: self.taker,
+                maker: immutables.maker,
+                taker: immutables.taker,
                 part_index,
                 secret,
                 amount,
             });
-        }
 
-        /// Refund remaining balance to taker after expiry
-        #[ink(message)]
-        pub fn refund(&mut self) {
+            Ok(())
+        }
+
+        /// Withdraw a single-part escrow with its secret, as in a plain HTLC
+        fn withdraw_with(&mut self, immutables: &Immutables, secret: Hash) -> Result<()> {
+            if immutables.parts_count != 1 {
+                return Err(Error::InvalidPartsCount);
+            }
+            self.claim_part_with(immutables, Vec::new(), secret, 0)
+        }
+
+        /// Claim several parts at once with a single Merkle multiproof
+        fn claim_parts_with(
+            &mut self,
+            immutables: &Immutables,
+            parts: Vec<(u32, Hash)>,
+            multiproof: MultiProof,
+        ) -> Result<()> {
+            self.ensure_withdrawal_open(immutables)?;
+
+            // Check the batch is not empty
+            if parts.is_empty() {
+                return Err(Error::InvalidPartIndex);
+            }
+
+            // Check if escrow is fully funded
+            if !self.is_funded_with(immutables) {
+                return Err(Error::NotFunded);
+            }
+
+            // Positional leaves cannot be combined without their positions, and a
+            // partial fill's escrow only releases its own secret
+            if immutables.proof_mode != ProofMode::SortedPairs || immutables.fill_index.is_some() {
+                return Err(Error::UnsupportedProofMode);
+            }
+
+            let mut leaves = Vec::with_capacity(parts.len());
+            let mut amounts = Vec::with_capacity(parts.len());
+            let mut total: Balance = 0;
+            let mut deposit: Balance = 0;
+            for (i, (part_index, secret)) in parts.iter().enumerate() {
+                // Check if part index is valid
+                if *part_index >= immutables.parts_count {
+                    return Err(Error::InvalidPartIndex);
+                }
+
+                // Check if part is already claimed, including earlier in this batch
+                if self.is_part_claimed(*part_index)
+                    || parts[..i].iter().any(|(other, _)| other == part_index)
+                {
+                    return Err(Error::AlreadyClaimed);
+                }
+
+                leaves.push(immutables.leaf(*secret, *part_index));
+                let amount = self.part_amount(immutables, *part_index)?;
+                amounts.push(amount);
+                total = total.saturating_add(amount);
+                deposit = deposit.saturating_add(Self::split(
+                    immutables.safety_deposit,
+                    immutables.parts_count,
+                    *part_index,
+                )?);
+            }
+
+            // Verify the multiproof
+            if self.process_multiproof(immutables, &leaves, &multiproof) != Some(immutables.merkle_root) {
+                return Err(Error::InvalidProof);
+            }
+
+            // Fills may already have paid out part of the total
+            if total > self.funded_amount.saturating_sub(self.filled_amount) {
+                return Err(Error::InvalidFillAmount);
+            }
+
+            // Transfer the sum to maker
+            if total > 0 {
+                self.transfer_asset(immutables, immutables.maker, total)?;
+            }
+
+            // Reward the caller with the claimed parts' share of the safety deposit
+            self.pay_safety_deposit(immutables, deposit)?;
+
+            self.filled_amount = self.filled_amount.saturating_add(total);
+            for ((part_index, secret), amount) in parts.into_iter().zip(amounts) {
+                // Mark the part claimed
+                self.mark_claimed(part_index, secret);
+
+                // Emit PartClaimed event
+                self.env().emit_event(PartClaimed {
+                    maker: immutables.maker,
+                    taker: immutables.taker,
+                    part_index,
+                    secret,
+                    amount,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Fill `amount` using the secret at `index` of the N+1 secrets tree
+        fn claim_fill_with(
+            &mut self,
+            immutables: &Immutables,
+            amount: Balance,
+            proof: Vec<Hash>,
+            secret: Hash,
+            index: u32,
+        ) -> Result<()> {
+            self.ensure_withdrawal_open(immutables)?;
+
+            // Check if escrow is fully funded
+            if !self.is_funded_with(immutables) {
+                return Err(Error::NotFunded);
+            }
+
+            // A hashlock has no extra secret to complete the fill with
+            if immutables.proof_mode == ProofMode::Hashlock {
+                return Err(Error::UnsupportedProofMode);
+            }
+
+            // Check the fill and the secret index it requires
+            if index != self.fill_secret_index(immutables, amount)? {
+                return Err(Error::InvalidSecretIndex);
+            }
+
+            // Each secret unlocks one fill
+            if self.is_part_claimed(index) {
+                return Err(Error::AlreadyClaimed);
+            }
+
+            // Verify Merkle proof
+            if !self.verify_merkle_proof(immutables, proof, secret, index) {
+                return Err(Error::InvalidProof);
+            }
+
+            // Transfer amount to maker
+            self.transfer_asset(immutables, immutables.maker, amount)?;
+
+            // Reward the caller with the filled share of the safety deposit
+            let filled_amount = self.filled_amount.saturating_add(amount);
+            let deposit = if filled_amount == self.funded_amount {
+                immutables.safety_deposit.saturating_sub(self.safety_deposit_paid)
+            } else {
+                immutables
+                    .safety_deposit
+                    .checked_mul(amount)
+                    .and_then(|share| share.checked_div(self.funded_amount))
+                    .unwrap_or_default()
+            };
+            self.pay_safety_deposit(immutables, deposit)?;
+
+            // Mark the secret used
+            self.mark_claimed(index, secret);
+            self.filled_amount = filled_amount;
+
+            // Emit PartClaimed event
+            self.env().emit_event(PartClaimed {
+                maker: immutables.maker,
+                taker: immutables.taker,
+                part_index: index,
+                secret,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        /// Refund remaining balance to taker once cancellation starts
+        fn refund_with(&mut self, immutables: &Immutables) -> Result<()> {
             // Check if escrow is not already refunded
             if self.refunded {
-                ink::env::debug_println!("Escrow already refunded");
-                return;
+                return Err(Error::Refunded);
+            }
+
+            // Refunds are only open in the cancellation stages
+            match self.current_stage_with(immutables) {
+                Stage::FinalityLock | Stage::ExclusiveWithdrawal | Stage::PublicWithdrawal => {
+                    return Err(Error::NotExpired)
+                }
+                Stage::ExclusiveCancellation => self.ensure_taker(immutables)?,
+                Stage::PublicCancellation => {}
+            }
+
+            // Mark as refunded
+            self.refunded = true;
+
+            // Transfer the unclaimed part of the locked amount to taker
+            let remaining_balance = self.funded_amount.saturating_sub(self.filled_amount);
+            if remaining_balance > 0 {
+                self.transfer_asset(immutables, immutables.taker, remaining_balance)?;
+            }
+
+            // Emit Refunded event
+            self.env().emit_event(Refunded {
+                taker: immutables.taker,
+                amount: remaining_balance,
+            });
+
+            // Reward the caller with the safety deposit left over from claims
+            self.pay_safety_deposit(
+                immutables,
+                immutables.safety_deposit.saturating_sub(self.safety_deposit_paid),
+            )?;
+
+            Ok(())
+        }
+
+        /// Stage the escrow is in at the current block
+        fn current_stage_with(&self, immutables: &Immutables) -> Stage {
+            let now = if immutables.time_unit.is_block_number() {
+                u64::from(self.env().block_number())
+            } else {
+                self.env().block_timestamp()
+            };
+            immutables
+                .timelocks
+                .stage_at(self.clock_start(immutables), now, immutables.time_unit)
+        }
+
+        /// Whether the escrow holds the expected amount
+        fn is_funded_with(&self, immutables: &Immutables) -> bool {
+            self.funded_amount >= immutables.amount
+        }
+
+        /// Indices of all claimed parts, in ascending order
+        fn claimed_parts_with(&self, immutables: &Immutables) -> Vec<u32> {
+            let mut parts = Vec::new();
+            // Up to index N, the extra secret of `claim_fill`
+            for word_index in 0..=immutables.parts_count / 64 {
+                let mut word = self.claimed_bitmap.get(word_index).unwrap_or_default();
+                while word != 0 {
+                    parts.push(word_index * 64 + word.trailing_zeros());
+                    // Clear the lowest set bit
+                    word &= word - 1;
+                }
+            }
+            parts
+        }
+
+        /// All revealed secrets with their indices, in ascending index order
+        fn get_revealed_secrets_with(&self, immutables: &Immutables) -> Vec<(u32, Hash)> {
+            self.claimed_parts_with(immutables)
+                .into_iter()
+                .filter_map(|index| Some((index, self.revealed_secrets.get(index)?)))
+                .collect()
+        }
+
+        /// Check claims and fills are open and allowed for the caller
+        fn ensure_withdrawal_open(&self, immutables: &Immutables) -> Result<()> {
+            // Check if escrow is not refunded
+            if self.refunded {
+                return Err(Error::Refunded);
+            }
+
+            // Claims are only open in the withdrawal stages
+            match self.current_stage_with(immutables) {
+                Stage::FinalityLock => Err(Error::FinalityLock),
+                Stage::ExclusiveWithdrawal => self.ensure_taker(immutables),
+                Stage::PublicWithdrawal => Ok(()),
+                Stage::ExclusiveCancellation | Stage::PublicCancellation => Err(Error::Expired),
+            }
+        }
+
+        /// Index of the secret a fill of `amount` must reveal
+        ///
+        /// The escrow of a partial fill is filled at once, with the secret at its
+        /// `fill_index`.
+        fn fill_secret_index(&self, immutables: &Immutables, amount: Balance) -> Result<u32> {
+            if let Some(fill_index) = immutables.fill_index {
+                if amount == 0 || amount != self.funded_amount.saturating_sub(self.filled_amount) {
+                    return Err(Error::InvalidFillAmount);
+                }
+                return Ok(fill_index);
+            }
+            fill_secret_index(
+                self.funded_amount,
+                immutables.parts_count,
+                self.filled_amount,
+                amount,
+            )
+            .ok_or(Error::InvalidFillAmount)
+        }
+
+        /// Deployment point on the escrow's clock
+        fn clock_start(&self, immutables: &Immutables) -> u64 {
+            if immutables.time_unit.is_block_number() {
+                u64::from(self.deployed_at_block)
+            } else {
+                self.deployed_at
+            }
+        }
+
+        /// Record the part or secret at `index` as used and store the revealed secret
+        fn mark_claimed(&mut self, index: u32, secret: Hash) {
+            let word = self.claimed_bitmap.get(index / 64).unwrap_or_default();
+            self.claimed_bitmap.insert(index / 64, &(word | 1 << (index % 64)));
+            self.revealed_secrets.insert(index, &secret);
+        }
+
+        /// Allow only the taker during exclusive stages
+        fn ensure_taker(&self, immutables: &Immutables) -> Result<()> {
+            if self.env().caller() != immutables.taker {
+                return Err(Error::NotTaker);
+            }
+            Ok(())
+        }
+
+        /// Record funding, rejecting zero value or value beyond the expected amount
+        fn lock_funds(&mut self, immutables: &Immutables, value: Balance) -> Result<()> {
+            let funded_amount = self
+                .funded_amount
+                .checked_add(value)
+                .ok_or(Error::InvalidFundingAmount)?;
+            if value == 0 || funded_amount > immutables.amount {
+                return Err(Error::InvalidFundingAmount);
+            }
+            self.funded_amount = funded_amount;
+
+            // Emit Funded event
+            self.env().emit_event(Funded {
+                from: self.env().caller(),
+                amount: value,
+                funded_amount,
+            });
+
+            Ok(())
+        }
+
+        /// Amount paid for a part, derived from the total locked at funding time
+        /// rather than the live balance, so every part is paid from the same pool
+        fn part_amount(&self, immutables: &Immutables, part_index: u32) -> Result<Balance> {
+            Self::split(self.funded_amount, immutables.parts_count, part_index)
+        }
+
+        /// Share of `total` for a part, the last part getting any remainder
+        fn split(total: Balance, parts_count: u32, part_index: u32) -> Result<Balance> {
+            let count = parts_count as u128;
+
+            // Use checked_div to avoid clippy warning
+            let amount_per_part = total.checked_div(count).ok_or(Error::InvalidPartsCount)?;
+
+            if part_index == parts_count.saturating_sub(1) {
+                // Last part gets any remainder
+                let other_parts = count.saturating_sub(1);
+                Ok(total.saturating_sub(amount_per_part.saturating_mul(other_parts)))
+            } else {
+                Ok(amount_per_part)
+            }
+        }
+
+        /// Whether the escrow holds the native balance rather than a token or asset
+        fn is_native(immutables: &Immutables) -> bool {
+            immutables.token.is_none() && immutables.asset_id.is_none()
+        }
+
+        /// Send escrowed funds natively, through a PSP22 `transfer` or through the
+        /// `pallet-assets` chain extension
+        fn transfer_asset(&self, immutables: &Immutables, to: AccountId, amount: Balance) -> Result<()> {
+            #[cfg(feature = "pallet-assets")]
+            if let Some(asset_id) = immutables.asset_id {
+                return self
+                    .env()
+                    .extension()
+                    .transfer(asset_id, to, amount)
+                    .map_err(|_| Error::TransferFailed);
+            }
+            let Some(token) = immutables.token else {
+                return self.env().transfer(to, amount).map_err(|_| Error::TransferFailed);
+            };
+
+            Self::call_psp22(token, Self::psp22_transfer(to, amount))
+        }
+
+        /// Input of PSP22 `transfer(to, value, data)` with empty data
+        fn psp22_transfer(to: AccountId, value: Balance) -> ExecutionInput<impl Encode> {
+            ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::transfer")))
+                .push_arg(to)
+                .push_arg(value)
+                .push_arg(Vec::<u8>::new())
+        }
+
+        /// Input of PSP22 `transfer_from(from, to, value, data)` with empty data
+        fn psp22_transfer_from(
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+        ) -> ExecutionInput<impl Encode> {
+            ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::transfer_from")))
+                .push_arg(from)
+                .push_arg(to)
+                .push_arg(value)
+                .push_arg(Vec::<u8>::new())
+        }
+
+        /// Call a PSP22 transfer message on `token`, failing on a reverted call or a
+        /// `Psp22Error`
+        fn call_psp22(token: AccountId, input: ExecutionInput<impl Encode>) -> Result<()> {
+            let transferred = build_call::<Environment>()
+                .call(token)
+                .exec_input(input)
+                .returns::<core::result::Result<(), Psp22Error>>()
+                .try_invoke();
+            match transferred {
+                Ok(Ok(Ok(()))) => Ok(()),
+                _ => Err(Error::TransferFailed),
+            }
+        }
+
+        /// Pay part of the safety deposit to the caller, never more than is left
+        fn pay_safety_deposit(&mut self, immutables: &Immutables, amount: Balance) -> Result<()> {
+            let amount = amount.min(immutables.safety_deposit.saturating_sub(self.safety_deposit_paid));
+            if amount == 0 {
+                return Ok(());
+            }
+
+            let caller = self.env().caller();
+            if self.env().transfer(caller, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.safety_deposit_paid = self.safety_deposit_paid.saturating_add(amount);
+
+            // Emit SafetyDepositPaid event
+            self.env().emit_event(SafetyDepositPaid { to: caller, amount });
+
+            Ok(())
+        }
+
+        /// Verify Merkle proof with the escrow's hash algorithm
+        fn verify_merkle_proof(
+            &self,
+            immutables: &Immutables,
+            proof: Vec<Hash>,
+            secret: Hash,
+            part_index: u32,
+        ) -> bool {
+            let mut current_hash = immutables.leaf(secret, part_index);
+
+            for (i, proof_hash) in proof.iter().enumerate() {
+                current_hash = match immutables.proof_mode {
+                    ProofMode::Positional => {
+                        let bit = (part_index >> i) & 1;
+                        if bit == 0 {
+                            // Current hash is left child
+                            Self::hash_pair(immutables, current_hash, *proof_hash)
+                        } else {
+                            // Current hash is right child
+                            Self::hash_pair(immutables, *proof_hash, current_hash)
+                        }
+                    }
+                    ProofMode::SortedPairs => Self::hash_sorted_pair(immutables, current_hash, *proof_hash),
+                    // A hashlock has no tree to climb
+                    ProofMode::Hashlock => return false,
+                };
+            }
+
+            current_hash == immutables.merkle_root
+        }
+
+        /// Compute the root from a multiproof as OpenZeppelin's `processMultiProof`,
+        /// returning `None` for a malformed proof
+        fn process_multiproof(
+            &self,
+            immutables: &Immutables,
+            leaves: &[Hash],
+            multiproof: &MultiProof,
+        ) -> Option<Hash> {
+            let MultiProof { proof, proof_flags } = multiproof;
+            if leaves.len().checked_add(proof.len())? != proof_flags.len().checked_add(1)? {
+                return None;
+            }
+
+            // Leaves are consumed first, then the hashes computed from them
+            let mut hashes = Vec::with_capacity(proof_flags.len());
+            let mut leaf_pos = 0;
+            let mut hash_pos = 0;
+            let mut proof_pos = 0;
+            let mut next_node = |hashes: &Vec<Hash>| {
+                if leaf_pos < leaves.len() {
+                    leaf_pos += 1;
+                    leaves.get(leaf_pos - 1).copied()
+                } else {
+                    hash_pos += 1;
+                    hashes.get(hash_pos - 1).copied()
+                }
+            };
+            for flag in proof_flags {
+                let a = next_node(&hashes)?;
+                let b = if *flag {
+                    next_node(&hashes)?
+                } else {
+                    proof_pos += 1;
+                    *proof.get(proof_pos - 1)?
+                };
+                hashes.push(Self::hash_sorted_pair(immutables, a, b));
+            }
+
+            if proof_flags.is_empty() {
+                return leaves.first().or(proof.first()).copied();
+            }
+            if proof_pos != proof.len() {
+                return None;
+            }
+            hashes.last().copied()
+        }
+
+        /// Hash a pair with the smaller hash first, as in OpenZeppelin's `_hashPair`
+        fn hash_sorted_pair(immutables: &Immutables, a: Hash, b: Hash) -> Hash {
+            if a.as_ref() <= b.as_ref() {
+                Self::hash_pair(immutables, a, b)
+            } else {
+                Self::hash_pair(immutables, b, a)
             }
+        }
+
+        /// Hash a pair of hashes with the escrow's hash algorithm
+        fn hash_pair(immutables: &Immutables, left: Hash, right: Hash) -> Hash {
+            let mut input = Vec::new();
+            input.extend_from_slice(left.as_ref());
+            input.extend_from_slice(right.as_ref());
+            immutables.hash_algorithm.hash(&input)
+        }
+    }
+
+    /// Messages of the default build, which reads the creation parameters from storage
+    impl EscrowDst {
+        /// Lock native balance into the escrow until the expected amount is reached
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message, payable)]
+        pub fn fund(&mut self) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.fund_with(&immutables)
+        }
+
+        /// Pull `value` PSP22 tokens or `pallet-assets` units from the caller until
+        /// the expected amount is reached
+        ///
+        /// The caller must have approved the escrow for `value` beforehand.
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn fund_token(&mut self, value: Balance) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.fund_token_with(&immutables, value)
+        }
+
+        /// Claim a part using Merkle proof and secret
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn claim_part(
+            &mut self,
+            proof: Vec<Hash>,
+            secret: Hash,
+            part_index: u32,
+        ) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.claim_part_with(&immutables, proof, secret, part_index)
+        }
+
+        /// Withdraw a single-part escrow with its secret, as in a plain HTLC
+        ///
+        /// Equivalent to `claim_part(vec![], secret, 0)`, so the same stage, caller and
+        /// safety deposit rules apply.
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn withdraw(&mut self, secret: Hash) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.withdraw_with(&immutables, secret)
+        }
+
+        /// Claim several parts at once with a single Merkle multiproof
+        ///
+        /// `parts` lists `(part_index, secret)` in the leaf order of the multiproof.
+        /// The parts are paid out in one transfer, with one `PartClaimed` event each.
+        /// Only available in `ProofMode::SortedPairs`.
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn claim_parts(&mut self, parts: Vec<(u32, Hash)>, multiproof: MultiProof) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.claim_parts_with(&immutables, parts, multiproof)
+        }
+
+        /// Fill `amount` using the secret at `index` of the N+1 secrets tree
+        ///
+        /// As in Fusion+, the index is the bucket the cumulative fill ends in:
+        /// `(filled_amount + amount - 1) * parts_count / total`. A fill that completes
+        /// the order must instead use the extra secret at index `parts_count`, and each
+        /// later fill must reach a new bucket. The escrow of a partial fill (see
+        /// `EscrowParams::fill_index`) is filled at once, with the secret at its
+        /// `fill_index`; parts can't be claimed from it.
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn claim_fill(
+            &mut self,
+            amount: Balance,
+            proof: Vec<Hash>,
+            secret: Hash,
+            index: u32,
+        ) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.claim_fill_with(&immutables, amount, proof, secret, index)
+        }
+
+        /// Refund remaining balance to taker once cancellation starts
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn refund(&mut self) -> Result<()> {
+            let immutables = self.immutables.clone();
+            self.refund_with(&immutables)
+        }
+
+        /// Get maker address
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_maker(&self) -> AccountId {
+            self.immutables.maker
+        }
+
+        /// Get taker address
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_taker(&self) -> AccountId {
+            self.immutables.taker
+        }
+
+        /// Get merkle root
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_merkle_root(&self) -> Hash {
+            self.immutables.merkle_root
+        }
+
+        /// Get the Merkle proof mode
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_proof_mode(&self) -> ProofMode {
+            self.immutables.proof_mode
+        }
+
+        /// Get the hash of the order the escrow settles
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_order_hash(&self) -> Hash {
+            self.immutables.order_hash
+        }
+
+        /// Get the secret index of the partial fill the escrow locks, `None` for an
+        /// escrow of the whole order
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_fill_index(&self) -> Option<u32> {
+            self.immutables.fill_index
+        }
+
+        /// Get the hash function used for hashlocks, leaves and nodes
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_hash_algorithm(&self) -> HashAlgorithm {
+            self.immutables.hash_algorithm
+        }
+
+        /// Get the prefix binding leaves to the escrow, `None` without domain separation
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_domain_separator(&self) -> Option<Hash> {
+            self.immutables.domain_separator()
+        }
+
+        /// Get parts count
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_parts_count(&self) -> u32 {
+            self.immutables.parts_count
+        }
+
+        /// Get the packed stage offsets
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_timelocks(&self) -> Timelocks {
+            self.immutables.timelocks
+        }
+
+        /// Get the unit the timelock offsets are expressed in
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_time_unit(&self) -> TimeUnit {
+            self.immutables.time_unit
+        }
+
+        /// Get the point at which `stage` starts on the escrow's clock: a block
+        /// timestamp in milliseconds, or a block number for `TimeUnit::Blocks`
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_stage_start(&self, stage: Stage) -> u64 {
+            let offset = self.immutables.timelocks.get_on_clock(stage, self.immutables.time_unit);
+            self.clock_start(&self.immutables).saturating_add(offset)
+        }
+
+        /// Get the timestamp at which `stage` starts in seconds, as Ethereum's
+        /// `block.timestamp`; `None` for block-number escrows
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_stage_start_seconds(&self, stage: Stage) -> Option<u64> {
+            if self.immutables.time_unit.is_block_number() {
+                return None;
+            }
+            Some(self.get_stage_start(stage) / 1000)
+        }
+
+        /// Get the stage the escrow is in at the current block
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn current_stage(&self) -> Stage {
+            self.current_stage_with(&self.immutables)
+        }
+
+        /// Get the PSP22 token escrowed, `None` for the native balance
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_token(&self) -> Option<AccountId> {
+            self.immutables.token
+        }
+
+        /// Get the `pallet-assets` asset escrowed, `None` unless in asset mode
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_asset_id(&self) -> Option<AssetId> {
+            self.immutables.asset_id
+        }
+
+        /// Get the amount the escrow must hold before parts can be claimed
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_amount(&self) -> Balance {
+            self.immutables.amount
+        }
+
+        /// Get the safety deposit posted by the taker
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_safety_deposit(&self) -> Balance {
+            self.immutables.safety_deposit
+        }
+
+        /// Whether the escrow holds the expected amount
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn is_funded(&self) -> bool {
+            self.is_funded_with(&self.immutables)
+        }
+
+        /// Get the indices of all claimed parts, in ascending order
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn claimed_parts(&self) -> Vec<u32> {
+            self.claimed_parts_with(&self.immutables)
+        }
+
+        /// Get all revealed secrets with their indices, in ascending index order
+        #[cfg(not(feature = "immutables-hash"))]
+        #[ink(message)]
+        pub fn get_revealed_secrets(&self) -> Vec<(u32, Hash)> {
+            self.get_revealed_secrets_with(&self.immutables)
+        }
+    }
+
+    /// Messages of the `immutables-hash` build, which take the creation parameters
+    /// from the caller and check them against the stored hash
+    ///
+    /// The namespace keeps their selectors apart from the default build's. ink! does not
+    /// forward `cfg` on impl blocks, so each message is gated on its own.
+    #[ink(namespace = "Immutables")]
+    impl EscrowDst {
+        /// Get the hash of the parameters the escrow was created with
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn get_immutables_hash(&self) -> Hash {
+            self.immutables_hash
+        }
+
+        /// Lock native balance into the escrow until the expected amount is reached
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message, payable)]
+        pub fn fund(&mut self, immutables: Immutables) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.fund_with(&immutables)
+        }
+
+        /// Pull `value` PSP22 tokens or `pallet-assets` units from the caller until
+        /// the expected amount is reached
+        ///
+        /// The caller must have approved the escrow for `value` beforehand.
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn fund_token(&mut self, immutables: Immutables, value: Balance) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.fund_token_with(&immutables, value)
+        }
+
+        /// Claim a part using Merkle proof and secret
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn claim_part(
+            &mut self,
+            immutables: Immutables,
+            proof: Vec<Hash>,
+            secret: Hash,
+            part_index: u32,
+        ) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.claim_part_with(&immutables, proof, secret, part_index)
+        }
+
+        /// Withdraw a single-part escrow with its secret, as in a plain HTLC
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn withdraw(&mut self, immutables: Immutables, secret: Hash) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.withdraw_with(&immutables, secret)
+        }
+
+        /// Claim several parts at once with a single Merkle multiproof
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn claim_parts(
+            &mut self,
+            immutables: Immutables,
+            parts: Vec<(u32, Hash)>,
+            multiproof: MultiProof,
+        ) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.claim_parts_with(&immutables, parts, multiproof)
+        }
+
+        /// Fill `amount` using the secret at `index` of the N+1 secrets tree
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn claim_fill(
+            &mut self,
+            immutables: Immutables,
+            amount: Balance,
+            proof: Vec<Hash>,
+            secret: Hash,
+            index: u32,
+        ) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.claim_fill_with(&immutables, amount, proof, secret, index)
+        }
+
+        /// Refund remaining balance to taker once cancellation starts
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn refund(&mut self, immutables: Immutables) -> Result<()> {
+            self.ensure_immutables(&immutables)?;
+            self.refund_with(&immutables)
+        }
+
+        /// Get the stage the escrow is in at the current block
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn current_stage(&self, immutables: Immutables) -> Result<Stage> {
+            self.ensure_immutables(&immutables)?;
+            Ok(self.current_stage_with(&immutables))
+        }
+
+        /// Whether the escrow holds the expected amount
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn is_funded(&self, immutables: Immutables) -> Result<bool> {
+            self.ensure_immutables(&immutables)?;
+            Ok(self.is_funded_with(&immutables))
+        }
+
+        /// Get the indices of all claimed parts, in ascending order
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn claimed_parts(&self, immutables: Immutables) -> Result<Vec<u32>> {
+            self.ensure_immutables(&immutables)?;
+            Ok(self.claimed_parts_with(&immutables))
+        }
+
+        /// Get all revealed secrets with their indices, in ascending index order
+        #[cfg(feature = "immutables-hash")]
+        #[ink(message)]
+        pub fn get_revealed_secrets(&self, immutables: Immutables) -> Result<Vec<(u32, Hash)>> {
+            self.ensure_immutables(&immutables)?;
+            Ok(self.get_revealed_secrets_with(&immutables))
+        }
+
+        /// Check `immutables` are the parameters the escrow was created with
+        #[cfg(feature = "immutables-hash")]
+        fn ensure_immutables(&self, immutables: &Immutables) -> Result<()> {
+            if immutables.hash() != self.immutables_hash {
+                return Err(Error::InvalidImmutables);
+            }
+            Ok(())
+        }
+    }
+
+    /// Unit tests
+    #[cfg(all(test, not(feature = "immutables-hash")))]
+    mod tests {
+        use super::*;
+        use ink::scale::Decode;
+        #[cfg(feature = "pallet-assets")]
+        use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+        fn test_params() -> EscrowParams {
+            EscrowParams {
+                maker: AccountId::from([1u8; 32]),
+                taker: AccountId::from([2u8; 32]),
+                merkle_root: Hash::from([3u8; 32]),
+                parts_count: 4,
+                // Taker-only claims until 1000, taker-only refunds after
+                timelocks: Timelocks::new(0, 1000, 1000, u32::MAX),
+                time_unit: TimeUnit::Milliseconds,
+                token: None,
+                asset_id: None,
+                amount: 400,
+                safety_deposit: 0,
+                proof_mode: ProofMode::Positional,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            }
+        }
+
+        /// Create the escrow funded with `params.amount` and the safety deposit,
+        /// with the taker as caller
+        fn create_escrow_with(params: EscrowParams) -> EscrowDst {
+            let taker = params.taker;
+
+            // Fund the escrow fully on instantiation
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(
+                params.amount + params.safety_deposit,
+            );
+            let escrow = EscrowDst::new(params).expect("escrow creation failed");
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(taker);
+            escrow
+        }
+
+        fn create_test_escrow() -> EscrowDst {
+            create_escrow_with(test_params())
+        }
+
+        fn create_unfunded_escrow() -> EscrowDst {
+            let escrow = EscrowDst::new(test_params()).expect("escrow creation failed");
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(AccountId::from([2u8; 32]));
+            escrow
+        }
+
+        /// Minimum balance the off-chain environment keeps on accounts
+        const EXISTENTIAL_DEPOSIT: Balance = 1_000_000;
+
+        fn test_secrets(count: u32) -> Vec<Hash> {
+            (0..count).map(|i| Hash::from([i as u8 + 10; 32])).collect()
+        }
+
+        fn keccak(input: &[u8]) -> Hash {
+            let mut output = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Keccak256>(input, &mut output);
+            Hash::from(output)
+        }
+
+        /// Build a Merkle tree over hashed secrets, padded to a power of two,
+        /// returning the root and one proof per secret
+        fn build_merkle_tree(secrets: &[Hash]) -> (Hash, Vec<Vec<Hash>>) {
+            let mut layer: Vec<Hash> = secrets.iter().map(|secret| keccak(secret.as_ref())).collect();
+            layer.resize(secrets.len().next_power_of_two(), Hash::from([0u8; 32]));
+
+            let mut proofs = vec![Vec::new(); secrets.len()];
+            let mut level = 0;
+            while layer.len() > 1 {
+                for (index, proof) in proofs.iter_mut().enumerate() {
+                    proof.push(layer[(index >> level) ^ 1]);
+                }
+                layer = layer
+                    .chunks(2)
+                    .map(|pair| keccak(&[pair[0].as_ref(), pair[1].as_ref()].concat()))
+                    .collect();
+                level += 1;
+            }
+
+            (layer[0], proofs)
+        }
+
+        /// Create an escrow at its own contract account holding `balance`, funded with `amount`
+        ///
+        /// The account is derived from the Merkle root, so escrows over different trees
+        /// in one test do not share off-chain storage.
+        fn deploy_funded_escrow(
+            merkle_root: Hash,
+            parts_count: u32,
+            proof_mode: ProofMode,
+            amount: Balance,
+            balance: Balance,
+        ) -> EscrowDst {
+            let contract = AccountId::from(<[u8; 32]>::from(merkle_root));
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract, balance);
+
+            create_escrow_with(EscrowParams {
+                merkle_root,
+                parts_count,
+                amount,
+                proof_mode,
+                ..test_params()
+            })
+        }
+
+        /// Create a positional-mode escrow with one part per secret
+        fn create_funded_escrow(secrets: &[Hash], amount: Balance, balance: Balance) -> EscrowDst {
+            let (merkle_root, _) = build_merkle_tree(secrets);
+            deploy_funded_escrow(merkle_root, secrets.len() as u32, ProofMode::Positional, amount, balance)
+        }
+
+        pub(super) fn hash_from_hex(hex: &str) -> Hash {
+            let hex = hex.trim_start_matches("0x");
+            let mut bytes = [0u8; 32];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("invalid hex");
+            }
+            Hash::from(bytes)
+        }
+
+        /// Vectors from `test-vectors/merkle-sorted-pairs.json`, also claimed against
+        /// `EscrowSrc` by `Ethereum/scripts/check-merkle-vectors.js`
+        pub(super) const SORTED_PAIRS_ROOT: &str =
+            "0x838a0a19acdaf18e65b0c0542c58fa916863fad5cef3a5cda625cdbf038f633a";
+        pub(super) const SORTED_PAIRS_PARTS: [(&str, &str, [&str; 2]); 4] = [
+            (
+                "0x1111111111111111111111111111111111111111111111111111111111111111",
+                "0x0aeb1c468b27b640d2b8a66dc9878c2f4359157650423cf979e1f983a5e08c1e",
+                [
+                    "0x37afa494ecbb4c4e10fca8e944fe4128b9c394f3535379cac72ad81a3a336f61",
+                    "0x7ac5c93bfd9c7c4bb38f0a3247a2cff112465095186f023577563712dcb6b599",
+                ],
+            ),
+            (
+                "0x2222222222222222222222222222222222222222222222222222222222222222",
+                "0x37afa494ecbb4c4e10fca8e944fe4128b9c394f3535379cac72ad81a3a336f61",
+                [
+                    "0x0aeb1c468b27b640d2b8a66dc9878c2f4359157650423cf979e1f983a5e08c1e",
+                    "0x7ac5c93bfd9c7c4bb38f0a3247a2cff112465095186f023577563712dcb6b599",
+                ],
+            ),
+            (
+                "0x3333333333333333333333333333333333333333333333333333333333333333",
+                "0xdbaa236c8fb5707157ddef8debfe74e4cdd2ecb0b4c404a350d41cda8deeb1a5",
+                [
+                    "0xce51db7b1cf02b2f96b6f132f76328ed4489b5b5ae7f2040c07fac21f7781b2f",
+                    "0x36ffde0c46230db340e16bb7c3bf978b3bda11ff96b29f57cbe5dd1d408130e3",
+                ],
+            ),
+            (
+                "0x4444444444444444444444444444444444444444444444444444444444444444",
+                "0xce51db7b1cf02b2f96b6f132f76328ed4489b5b5ae7f2040c07fac21f7781b2f",
+                [
+                    "0xdbaa236c8fb5707157ddef8debfe74e4cdd2ecb0b4c404a350d41cda8deeb1a5",
+                    "0x36ffde0c46230db340e16bb7c3bf978b3bda11ff96b29f57cbe5dd1d408130e3",
+                ],
+            ),
+        ];
+
+        fn balance_of(account: AccountId) -> Balance {
+            ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(account)
+                .expect("account has no balance")
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        /// `pallet-assets` balances and approvals (to the escrow) kept by `MockAssets`
+        #[derive(Default)]
+        struct AssetLedger {
+            balances: HashMap<(AssetId, AccountId), Balance>,
+            approvals: HashMap<(AssetId, AccountId), Balance>,
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        impl AssetLedger {
+            fn balance(&self, asset_id: AssetId, account: AccountId) -> Balance {
+                self.balances.get(&(asset_id, account)).copied().unwrap_or_default()
+            }
+
+            fn move_balance(
+                &mut self,
+                asset_id: AssetId,
+                from: AccountId,
+                to: AccountId,
+                amount: Balance,
+            ) -> bool {
+                let Some(remaining) = self.balance(asset_id, from).checked_sub(amount) else {
+                    return false;
+                };
+                self.balances.insert((asset_id, from), remaining);
+                *self.balances.entry((asset_id, to)).or_default() += amount;
+                true
+            }
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        /// Off-chain stand-in for the runtime's `pallet-assets` chain extension
+        struct MockAssets {
+            escrow: AccountId,
+            ledger: Rc<RefCell<AssetLedger>>,
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        impl ink::env::test::ChainExtension for MockAssets {
+            fn ext_id(&self) -> u16 {
+                1
+            }
+
+            fn call(&mut self, func_id: u16, input: &[u8], _output: &mut Vec<u8>) -> u32 {
+                // The off-chain engine hands over the encoded arguments as a SCALE `Vec<u8>`
+                let input = Vec::<u8>::decode(&mut &input[..]).expect("invalid input");
+                let mut input = &input[..];
+                let mut ledger = self.ledger.borrow_mut();
+                let moved = match func_id {
+                    // transfer
+                    1 => {
+                        let (asset_id, to, amount) =
+                            <(AssetId, AccountId, Balance)>::decode(&mut input).expect("invalid input");
+                        ledger.move_balance(asset_id, self.escrow, to, amount)
+                    }
+                    // transfer_approved
+                    2 => {
+                        let (asset_id, owner, to, amount) =
+                            <(AssetId, AccountId, AccountId, Balance)>::decode(&mut input)
+                                .expect("invalid input");
+                        let approved = ledger.approvals.get(&(asset_id, owner)).copied().unwrap_or_default();
+                        match approved.checked_sub(amount) {
+                            Some(left) if ledger.move_balance(asset_id, owner, to, amount) => {
+                                ledger.approvals.insert((asset_id, owner), left);
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                };
+                if moved { 0 } else { 1 }
+            }
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        /// Register `MockAssets` for an escrow at `escrow`, returning its ledger
+        fn register_mock_assets(escrow: AccountId) -> Rc<RefCell<AssetLedger>> {
+            let ledger = Rc::new(RefCell::new(AssetLedger::default()));
+            ink::env::test::register_chain_extension(MockAssets {
+                escrow,
+                ledger: ledger.clone(),
+            });
+            ledger
+        }
+
+        #[ink::test]
+        fn test_new() {
+            let escrow = create_test_escrow();
+            
+            assert_eq!(escrow.get_maker(), AccountId::from([1u8; 32]));
+            assert_eq!(escrow.get_taker(), AccountId::from([2u8; 32]));
+            assert_eq!(escrow.get_merkle_root(), Hash::from([3u8; 32]));
+            assert_eq!(escrow.get_parts_count(), 4);
+            assert_eq!(escrow.get_timelocks(), Timelocks::new(0, 1000, 1000, u32::MAX));
+            assert_eq!(escrow.get_deployed_at(), 0);
+            assert!(escrow.claimed_parts().is_empty());
+            assert!(!escrow.get_refunded());
+            assert_eq!(escrow.get_amount(), 400);
+            assert_eq!(escrow.get_funded_amount(), 400);
+            assert!(escrow.is_funded());
+        }
+
+        #[ink::test]
+        fn test_storage_footprint() {
+            use ink::storage::traits::Storable;
+            let escrow = create_test_escrow();
+
+            // Root storage cell; the creation parameters take up most of it, see
+            // `immutables_hash_tests::test_storage_footprint` for the hash-only layout
+            assert_eq!(ink::scale::Encode::encoded_size(&test_params()), 187);
+            assert_eq!(Storable::encoded_size(&escrow), 248);
+        }
+
+        #[ink::test]
+        fn test_new_rejects_excess_funding() {
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(401);
+            let result = EscrowDst::new(test_params());
+            assert_eq!(result.err(), Some(Error::InvalidFundingAmount));
+        }
+
+        #[ink::test]
+        fn test_fund() {
+            let mut escrow = create_unfunded_escrow();
+            assert!(!escrow.is_funded());
+
+            // Partial funding
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(150);
+            assert_eq!(escrow.fund(), Ok(()));
+            assert_eq!(escrow.get_funded_amount(), 150);
+            assert!(!escrow.is_funded());
+
+            // Funding beyond the expected amount is rejected
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(251);
+            assert_eq!(escrow.fund(), Err(Error::InvalidFundingAmount));
+
+            // Zero value is rejected
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            assert_eq!(escrow.fund(), Err(Error::InvalidFundingAmount));
+
+            // Remaining funding completes the escrow
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(250);
+            assert_eq!(escrow.fund(), Ok(()));
+            assert_eq!(escrow.get_funded_amount(), 400);
+            assert!(escrow.is_funded());
+
+            // No further funding once complete
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1);
+            assert_eq!(escrow.fund(), Err(Error::AlreadyFunded));
+
+            // DstCreated plus two Funded events
+            let events = ink::env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(events.len(), 3);
+        }
+
+        #[ink::test]
+        fn test_claim_before_funded() {
+            let mut escrow = create_unfunded_escrow();
+
+            assert_eq!(
+                escrow.claim_part(vec![], Hash::from([1u8; 32]), 0),
+                Err(Error::NotFunded)
+            );
+        }
+
+        #[ink::test]
+        fn test_invalid_part_index() {
+            let mut escrow = create_test_escrow();
+            assert_eq!(
+                escrow.claim_part(vec![], Hash::from([1u8; 32]), 5),
+                Err(Error::InvalidPartIndex)
+            );
+            // Should not claim anything due to invalid index
+            assert!(escrow.claimed_parts().is_empty());
+        }
+
+        #[ink::test]
+        fn test_part_already_claimed() {
+            let maker = AccountId::from([1u8; 32]);
+            let secrets = test_secrets(4);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 400, 400 + EXISTENTIAL_DEPOSIT);
+            assert_eq!(escrow.claim_part(proofs[2].clone(), secrets[2], 2), Ok(()));
+
+            // Revealing the same valid secret again pays nothing
+            let maker_before = balance_of(maker);
+            assert_eq!(
+                escrow.claim_part(proofs[2].clone(), secrets[2], 2),
+                Err(Error::AlreadyClaimed)
+            );
+            assert_eq!(balance_of(maker), maker_before);
+            assert_eq!(escrow.claimed_parts(), vec![2]);
+            assert_eq!(escrow.get_filled_amount(), 100);
+        }
+
+        #[ink::test]
+        fn test_skipped_part_claimable_after_later_part() {
+            let maker = AccountId::from([1u8; 32]);
+            let secrets = test_secrets(4);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 400, 400 + EXISTENTIAL_DEPOSIT);
+
+            // Part 2 first skips part 0, which stays claimable
+            assert_eq!(escrow.claim_part(proofs[2].clone(), secrets[2], 2), Ok(()));
+            assert!(!escrow.is_part_claimed(0));
+
+            let maker_before = balance_of(maker);
+            assert_eq!(escrow.claim_part(proofs[0].clone(), secrets[0], 0), Ok(()));
+            assert_eq!(balance_of(maker) - maker_before, 100);
+            assert_eq!(escrow.claimed_parts(), vec![0, 2]);
+            assert_eq!(escrow.get_filled_amount(), 200);
+        }
+
+        #[ink::test]
+        fn test_out_of_order_claims() {
+            let maker = AccountId::from([1u8; 32]);
+            let secrets = test_secrets(4);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 400, 400 + EXISTENTIAL_DEPOSIT);
+
+            // Later parts first, the skipped part stays claimable
+            for part_index in [3u32, 1, 0] {
+                let index = part_index as usize;
+                assert_eq!(
+                    escrow.claim_part(proofs[index].clone(), secrets[index], part_index),
+                    Ok(())
+                );
+            }
+            assert_eq!(escrow.claimed_parts(), vec![0, 1, 3]);
+            assert!(escrow.is_part_claimed(3));
+            assert!(!escrow.is_part_claimed(2));
+            assert_eq!(escrow.get_filled_amount(), 300);
+
+            assert_eq!(
+                escrow.claim_part(proofs[1].clone(), secrets[1], 1),
+                Err(Error::AlreadyClaimed)
+            );
+
+            let maker_before = balance_of(maker);
+            assert_eq!(escrow.claim_part(proofs[2].clone(), secrets[2], 2), Ok(()));
+            assert_eq!(balance_of(maker) - maker_before, 100);
+            assert_eq!(escrow.claimed_parts(), vec![0, 1, 2, 3]);
+            assert_eq!(escrow.get_filled_amount(), 400);
+        }
+
+        #[ink::test]
+        fn test_claimed_bitmap_spans_words() {
+            let secrets = test_secrets(70);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 700, 700 + EXISTENTIAL_DEPOSIT);
+
+            for part_index in [69u32, 64, 63, 0] {
+                let index = part_index as usize;
+                assert_eq!(
+                    escrow.claim_part(proofs[index].clone(), secrets[index], part_index),
+                    Ok(())
+                );
+            }
+            assert_eq!(escrow.claimed_parts(), vec![0, 63, 64, 69]);
+            assert!(escrow.is_part_claimed(64));
+            assert!(!escrow.is_part_claimed(65));
+        }
+
+        #[ink::test]
+        fn test_revealed_secrets_registry() {
+            let secrets = test_secrets(4);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 400, 400 + EXISTENTIAL_DEPOSIT);
+            assert!(escrow.get_revealed_secrets().is_empty());
+
+            for part_index in [2u32, 0] {
+                let index = part_index as usize;
+                assert_eq!(
+                    escrow.claim_part(proofs[index].clone(), secrets[index], part_index),
+                    Ok(())
+                );
+            }
+
+            // A rejected claim reveals nothing
+            assert_eq!(
+                escrow.claim_part(proofs[1].clone(), secrets[3], 1),
+                Err(Error::InvalidProof)
+            );
+
+            assert_eq!(escrow.get_revealed_secret(2), Some(secrets[2]));
+            assert_eq!(escrow.get_revealed_secret(1), None);
+            assert_eq!(
+                escrow.get_revealed_secrets(),
+                vec![(0, secrets[0]), (2, secrets[2])]
+            );
+        }
+
+        #[ink::test]
+        fn test_revealed_fill_secret_at_index_n() {
+            // N = 64 parts puts the extra secret in the second bitmap word
+            let secrets = test_secrets(65);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = deploy_funded_escrow(
+                merkle_root,
+                64,
+                ProofMode::Positional,
+                6400,
+                6400 + EXISTENTIAL_DEPOSIT,
+            );
+
+            assert_eq!(escrow.claim_fill(6400, proofs[64].clone(), secrets[64], 64), Ok(()));
+            assert_eq!(escrow.claimed_parts(), vec![64]);
+            assert_eq!(escrow.get_revealed_secrets(), vec![(64, secrets[64])]);
+        }
+
+        #[ink::test]
+        fn test_claim_fill_follows_cumulative_index() {
+            let maker = AccountId::from([1u8; 32]);
+            // N = 4 parts, N + 1 secrets
+            let secrets = test_secrets(5);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = deploy_funded_escrow(
+                merkle_root,
+                4,
+                ProofMode::Positional,
+                400,
+                400 + EXISTENTIAL_DEPOSIT,
+            );
+            let maker_before = balance_of(maker);
+
+            // 0 -> 150 ends in bucket 1
+            assert_eq!(
+                escrow.claim_fill(150, proofs[0].clone(), secrets[0], 0),
+                Err(Error::InvalidSecretIndex)
+            );
+            assert_eq!(escrow.claim_fill(150, proofs[1].clone(), secrets[1], 1), Ok(()));
+
+            // 150 -> 200 stays in bucket 1, no new secret to reveal
+            assert_eq!(
+                escrow.claim_fill(50, proofs[1].clone(), secrets[1], 1),
+                Err(Error::InvalidFillAmount)
+            );
+
+            // 150 -> 250 ends in bucket 2
+            assert_eq!(escrow.claim_fill(100, proofs[2].clone(), secrets[2], 2), Ok(()));
+
+            // 250 -> 400 completes the order, which only the extra secret allows
+            assert_eq!(
+                escrow.claim_fill(150, proofs[3].clone(), secrets[3], 3),
+                Err(Error::InvalidSecretIndex)
+            );
+            assert_eq!(escrow.claim_fill(150, proofs[4].clone(), secrets[4], 4), Ok(()));
+
+            assert_eq!(escrow.get_filled_amount(), 400);
+            assert_eq!(escrow.claimed_parts(), vec![1, 2, 4]);
+            assert_eq!(balance_of(maker) - maker_before, 400);
+            assert_eq!(escrow.get_balance(), EXISTENTIAL_DEPOSIT);
+        }
+
+        #[ink::test]
+        fn test_claim_fill_rejects_invalid_amounts() {
+            let secrets = test_secrets(5);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = deploy_funded_escrow(
+                merkle_root,
+                4,
+                ProofMode::Positional,
+                400,
+                400 + EXISTENTIAL_DEPOSIT,
+            );
+
+            assert_eq!(
+                escrow.claim_fill(0, proofs[0].clone(), secrets[0], 0),
+                Err(Error::InvalidFillAmount)
+            );
+            assert_eq!(
+                escrow.claim_fill(401, proofs[4].clone(), secrets[4], 4),
+                Err(Error::InvalidFillAmount)
+            );
+
+            // A single full fill uses the extra secret directly
+            assert_eq!(escrow.claim_fill(400, proofs[4].clone(), secrets[4], 4), Ok(()));
+            assert_eq!(
+                escrow.claim_fill(1, proofs[4].clone(), secrets[4], 4),
+                Err(Error::InvalidFillAmount)
+            );
+        }
+
+        #[ink::test]
+        fn test_claim_fill_completes_with_extra_secret_below_parts_count() {
+            // Fewer units than parts: 2 units over N = 4 parts
+            let secrets = test_secrets(5);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = deploy_funded_escrow(
+                merkle_root,
+                4,
+                ProofMode::Positional,
+                2,
+                2 + EXISTENTIAL_DEPOSIT,
+            );
+
+            // 0 -> 1 ends in bucket 0
+            assert_eq!(escrow.claim_fill(1, proofs[0].clone(), secrets[0], 0), Ok(()));
+
+            // 1 -> 2 completes the order, which still only the extra secret allows
+            assert_eq!(
+                escrow.claim_fill(1, proofs[3].clone(), secrets[3], 3),
+                Err(Error::InvalidSecretIndex)
+            );
+            assert_eq!(escrow.claim_fill(1, proofs[4].clone(), secrets[4], 4), Ok(()));
+            assert_eq!(escrow.get_filled_amount(), 2);
+            assert_eq!(escrow.claimed_parts(), vec![0, 4]);
+        }
+
+        #[ink::test]
+        fn test_claim_fill_pays_safety_deposit_pro_rata() {
+            let resolver = AccountId::from([5u8; 32]);
+            let secrets = test_secrets(5);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let contract = AccountId::from([0x42; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                400 + 41 + EXISTENTIAL_DEPOSIT,
+            );
+            let mut escrow = create_escrow_with(EscrowParams {
+                merkle_root,
+                timelocks: Timelocks::new(0, 0, 1000, u32::MAX),
+                safety_deposit: 41,
+                ..test_params()
+            });
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(resolver);
+
+            let resolver_before = balance_of(resolver);
+            assert_eq!(escrow.claim_fill(100, proofs[0].clone(), secrets[0], 0), Ok(()));
+            assert_eq!(balance_of(resolver) - resolver_before, 10);
+
+            // The completing fill takes whatever is left of the deposit
+            assert_eq!(escrow.claim_fill(300, proofs[4].clone(), secrets[4], 4), Ok(()));
+            assert_eq!(balance_of(resolver) - resolver_before, 41);
+            assert_eq!(escrow.get_balance(), EXISTENTIAL_DEPOSIT);
+        }
+
+        #[ink::test]
+        fn test_claim_part_cannot_overpay_fills() {
+            let secrets = test_secrets(5);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = deploy_funded_escrow(
+                merkle_root,
+                4,
+                ProofMode::Positional,
+                400,
+                400 + EXISTENTIAL_DEPOSIT,
+            );
+
+            // 0 -> 350 ends in bucket 3, leaving 50 unfilled
+            assert_eq!(escrow.claim_fill(350, proofs[3].clone(), secrets[3], 3), Ok(()));
+            assert_eq!(
+                escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                Err(Error::InvalidFillAmount)
+            );
+        }
+
+        #[ink::test]
+        fn test_fill_escrow_reveals_only_its_secret() {
+            let maker = AccountId::from([1u8; 32]);
+            // The escrow of a 100 fill ending at secret 2 of an order's N + 1 secrets
+            let secrets = test_secrets(5);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let contract = AccountId::from([0x42; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                100 + EXISTENTIAL_DEPOSIT,
+            );
+            let mut escrow = create_escrow_with(EscrowParams {
+                merkle_root,
+                amount: 100,
+                fill_index: Some(2),
+                ..test_params()
+            });
+            assert_eq!(escrow.get_fill_index(), Some(2));
+
+            // Secrets revealed for other fills of the order don't unlock it
+            assert_eq!(
+                escrow.claim_fill(100, proofs[0].clone(), secrets[0], 0),
+                Err(Error::InvalidSecretIndex)
+            );
+            assert_eq!(
+                escrow.claim_fill(100, proofs[4].clone(), secrets[4], 4),
+                Err(Error::InvalidSecretIndex)
+            );
+            assert_eq!(
+                escrow.claim_fill(100, proofs[0].clone(), secrets[0], 2),
+                Err(Error::InvalidProof)
+            );
+
+            // Nor do parts, and the fill is taken at once
+            assert_eq!(
+                escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                Err(Error::UnsupportedProofMode)
+            );
+            assert_eq!(
+                escrow.claim_fill(50, proofs[2].clone(), secrets[2], 2),
+                Err(Error::InvalidFillAmount)
+            );
+
+            let maker_before = balance_of(maker);
+            assert_eq!(escrow.claim_fill(100, proofs[2].clone(), secrets[2], 2), Ok(()));
+            assert_eq!(balance_of(maker) - maker_before, 100);
+            assert_eq!(escrow.get_revealed_secrets(), vec![(2, secrets[2])]);
+        }
+
+        #[ink::test]
+        fn test_fill_escrow_rejects_invalid_fill_index() {
+            let result = EscrowDst::new(EscrowParams { fill_index: Some(5), ..test_params() });
+            assert_eq!(result.err(), Some(Error::InvalidSecretIndex));
+
+            let result = EscrowDst::new(EscrowParams {
+                parts_count: 1,
+                proof_mode: ProofMode::Hashlock,
+                fill_index: Some(0),
+                ..test_params()
+            });
+            assert_eq!(result.err(), Some(Error::UnsupportedProofMode));
+        }
+
+        #[ink::test]
+        fn test_escrow_expired() {
+            let mut escrow = EscrowDst::new(EscrowParams { amount: 0, ..test_params() })
+                .expect("escrow creation failed");
+            
+            // Set block timestamp to after expiry
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            
+            assert_eq!(
+                escrow.claim_part(vec![], Hash::from([1u8; 32]), 0),
+                Err(Error::Expired)
+            );
+            // Should not claim anything due to expiry
+            assert!(escrow.claimed_parts().is_empty());
+        }
+
+        #[ink::test]
+        fn test_refund_before_expiry() {
+            let mut escrow = create_test_escrow();
+            
+            // Set block timestamp to before expiry
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(999);
+            
+            assert_eq!(escrow.refund(), Err(Error::NotExpired));
+            // Should not refund due to not expired
+            assert!(!escrow.get_refunded());
+        }
+
+        #[ink::test]
+        fn test_refund_after_expiry() {
+            let mut escrow = create_test_escrow();
+            
+            // Set block timestamp to after expiry
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            
+            assert_eq!(escrow.refund(), Ok(()));
+            // Should refund after expiry
+            assert!(escrow.get_refunded());
+        }
+
+        #[ink::test]
+        fn test_double_refund() {
+            let mut escrow = create_test_escrow();
+            
+            // Set block timestamp to after expiry
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            
+            // First refund should succeed
+            assert_eq!(escrow.refund(), Ok(()));
+            assert!(escrow.get_refunded());
+            
+            // Second refund should not change state
+            assert_eq!(escrow.refund(), Err(Error::Refunded));
+            assert!(escrow.get_refunded());
+        }
+
+        #[ink::test]
+        fn test_claim_after_refund() {
+            let mut escrow = create_test_escrow();
+            
+            // Set block timestamp to after expiry
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            
+            // Refund the escrow
+            assert_eq!(escrow.refund(), Ok(()));
+            assert!(escrow.get_refunded());
+            
+            // Try to claim after refund
+            assert_eq!(
+                escrow.claim_part(vec![], Hash::from([1u8; 32]), 0),
+                Err(Error::Refunded)
+            );
+            // Should not claim anything due to refunded state
+            assert!(escrow.claimed_parts().is_empty());
+        }
+
+        #[ink::test]
+        fn test_new_rejects_unordered_timelocks() {
+            for timelocks in [
+                Timelocks::new(1001, 1000, 1000, u32::MAX),
+                Timelocks::new(0, 1001, 1000, u32::MAX),
+                Timelocks::new(0, 1000, 1000, 999),
+            ] {
+                let result = EscrowDst::new(EscrowParams { timelocks, ..test_params() });
+                assert_eq!(result.err(), Some(Error::InvalidTimelocks));
+            }
+        }
+
+        #[ink::test]
+        fn test_hashlock_requires_single_part() {
+            let result = EscrowDst::new(EscrowParams {
+                proof_mode: ProofMode::Hashlock,
+                ..test_params()
+            });
+            assert_eq!(result.err(), Some(Error::InvalidPartsCount));
+        }
+
+        #[ink::test]
+        fn test_hashlock_withdraw() {
+            let maker = AccountId::from([1u8; 32]);
+            let secret = Hash::from([9u8; 32]);
+            let hashlock = keccak(secret.as_ref());
+            let mut escrow =
+                deploy_funded_escrow(hashlock, 1, ProofMode::Hashlock, 400, 400 + EXISTENTIAL_DEPOSIT);
+            assert_eq!(escrow.get_proof_mode(), ProofMode::Hashlock);
+
+            assert_eq!(escrow.withdraw(Hash::from([8u8; 32])), Err(Error::InvalidProof));
+
+            let maker_before = balance_of(maker);
+            assert_eq!(escrow.withdraw(secret), Ok(()));
+            assert_eq!(balance_of(maker) - maker_before, 400);
+            assert_eq!(escrow.claimed_parts(), vec![0]);
+            assert_eq!(escrow.withdraw(secret), Err(Error::AlreadyClaimed));
+        }
+
+        #[ink::test]
+        fn test_hashlock_rejects_proofs_and_fills() {
+            let secret = Hash::from([10u8; 32]);
+            let hashlock = keccak(secret.as_ref());
+            let mut escrow =
+                deploy_funded_escrow(hashlock, 1, ProofMode::Hashlock, 400, 400 + EXISTENTIAL_DEPOSIT);
+
+            assert_eq!(
+                escrow.claim_part(vec![hashlock], secret, 0),
+                Err(Error::InvalidProof)
+            );
+            assert_eq!(
+                escrow.claim_fill(400, vec![], secret, 1),
+                Err(Error::UnsupportedProofMode)
+            );
+            assert_eq!(escrow.claim_part(vec![], secret, 0), Ok(()));
+        }
+
+        #[ink::test]
+        fn test_withdraw_requires_single_part() {
+            let secrets = test_secrets(4);
+            let mut escrow = create_funded_escrow(&secrets, 400, 400 + EXISTENTIAL_DEPOSIT);
+            assert_eq!(escrow.withdraw(secrets[0]), Err(Error::InvalidPartsCount));
+        }
+
+        #[ink::test]
+        fn test_timelocks_packing() {
+            let timelocks = Timelocks::new(1, 2, 3, u32::MAX);
+            assert_eq!(timelocks.withdrawal(), 1);
+            assert_eq!(timelocks.public_withdrawal(), 2);
+            assert_eq!(timelocks.cancellation(), 3);
+            assert_eq!(timelocks.public_cancellation(), u32::MAX);
+            assert_eq!(timelocks.get(Stage::FinalityLock), 0);
+            assert_eq!(timelocks.get(Stage::PublicWithdrawal), 2);
+        }
+
+        #[ink::test]
+        fn test_stages_are_relative_to_deployment() {
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(10_000);
+            let escrow = create_escrow_with(EscrowParams {
+                timelocks: Timelocks::new(100, 200, 300, 400),
+                ..test_params()
+            });
+            assert_eq!(escrow.get_deployed_at(), 10_000);
+            assert_eq!(escrow.get_stage_start(Stage::FinalityLock), 10_000);
+            assert_eq!(escrow.get_stage_start(Stage::PublicCancellation), 10_400);
+
+            for (timestamp, stage) in [
+                (10_000, Stage::FinalityLock),
+                (10_099, Stage::FinalityLock),
+                (10_100, Stage::ExclusiveWithdrawal),
+                (10_200, Stage::PublicWithdrawal),
+                (10_300, Stage::ExclusiveCancellation),
+                (10_399, Stage::ExclusiveCancellation),
+                (10_400, Stage::PublicCancellation),
+                (u64::MAX, Stage::PublicCancellation),
+            ] {
+                ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(timestamp);
+                assert_eq!(escrow.current_stage(), stage);
+            }
+        }
+
+        #[ink::test]
+        fn test_seconds_time_unit() {
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_700_000_000_000);
+            let escrow = create_escrow_with(EscrowParams {
+                timelocks: Timelocks::new(10, 20, 30, 40),
+                time_unit: TimeUnit::Seconds,
+                ..test_params()
+            });
+            assert_eq!(escrow.get_time_unit(), TimeUnit::Seconds);
+            assert_eq!(escrow.get_deployed_at(), 1_700_000_000_000);
+            assert_eq!(escrow.get_deployed_at_seconds(), 1_700_000_000);
+            assert_eq!(
+                escrow.get_stage_start(Stage::ExclusiveCancellation),
+                1_700_000_030_000
+            );
+            assert_eq!(
+                escrow.get_stage_start_seconds(Stage::ExclusiveCancellation),
+                Some(1_700_000_030)
+            );
+
+            for (timestamp, stage) in [
+                (1_700_000_009_999, Stage::FinalityLock),
+                (1_700_000_010_000, Stage::ExclusiveWithdrawal),
+                (1_700_000_029_999, Stage::PublicWithdrawal),
+                (1_700_000_030_000, Stage::ExclusiveCancellation),
+                (1_700_000_040_000, Stage::PublicCancellation),
+            ] {
+                ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(timestamp);
+                assert_eq!(escrow.current_stage(), stage);
+            }
+        }
+
+        #[ink::test]
+        fn test_block_number_time_unit() {
+            let secrets = test_secrets(4);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+            let mut escrow = deploy_escrow_with(EscrowParams {
+                merkle_root,
+                timelocks: Timelocks::new(2, 4, 6, 8),
+                time_unit: TimeUnit::Blocks,
+                ..test_params()
+            });
+            assert_eq!(escrow.get_deployed_at_block(), 1);
+            assert_eq!(escrow.get_stage_start(Stage::ExclusiveCancellation), 7);
+            assert_eq!(escrow.get_stage_start_seconds(Stage::ExclusiveCancellation), None);
+
+            // Timestamps do not move the stages
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(u64::from(u32::MAX));
+            assert_eq!(escrow.current_stage(), Stage::FinalityLock);
+            assert_eq!(
+                escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                Err(Error::FinalityLock)
+            );
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(0);
+
+            for (block, stage) in [
+                (2, Stage::FinalityLock),
+                (3, Stage::ExclusiveWithdrawal),
+                (5, Stage::PublicWithdrawal),
+                (7, Stage::ExclusiveCancellation),
+                (9, Stage::PublicCancellation),
+            ] {
+                while ink::env::block_number::<ink::env::DefaultEnvironment>() < block {
+                    ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+                }
+                assert_eq!(escrow.current_stage(), stage);
+
+                match stage {
+                    Stage::ExclusiveWithdrawal => assert_eq!(
+                        escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                        Ok(())
+                    ),
+                    Stage::ExclusiveCancellation => {
+                        assert_eq!(
+                            escrow.claim_part(proofs[1].clone(), secrets[1], 1),
+                            Err(Error::Expired)
+                        );
+                        assert_eq!(escrow.refund(), Ok(()));
+                    }
+                    _ => {}
+                }
+            }
+            assert_eq!(escrow.claimed_parts(), vec![0]);
+            assert!(escrow.get_refunded());
+        }
+
+        #[ink::test]
+        fn test_new_rejects_out_of_bounds_timelocks() {
+            for (timelocks, time_unit) in [
+                // Refundable right away
+                (Timelocks::new(0, 0, 0, 1000), TimeUnit::Milliseconds),
+                // An absolute `block.timestamp` passed as an offset
+                (Timelocks::new(0, 0, 1_700_000_000, 1_700_000_000), TimeUnit::Seconds),
+                // Just over 90 days
+                (Timelocks::new(0, 0, 1000, 7_776_001), TimeUnit::Seconds),
+                (Timelocks::new(0, 0, 1000, 1_296_001), TimeUnit::Blocks),
+            ] {
+                let result = EscrowDst::new(EscrowParams {
+                    timelocks,
+                    time_unit,
+                    ..test_params()
+                });
+                assert_eq!(result.err(), Some(Error::TimelocksOutOfBounds));
+            }
+
+            // Exactly 90 days is allowed
+            let result = EscrowDst::new(EscrowParams {
+                timelocks: Timelocks::new(0, 0, 1000, 7_776_000),
+                time_unit: TimeUnit::Seconds,
+                ..test_params()
+            });
+            assert!(result.is_ok());
+        }
+
+        #[ink::test]
+        fn test_finality_lock_blocks_claims_and_refunds() {
+            let secrets = test_secrets(4);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_escrow_with(EscrowParams {
+                merkle_root,
+                timelocks: Timelocks::new(100, 1000, 1000, u32::MAX),
+                ..test_params()
+            });
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(99);
+            assert_eq!(
+                escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                Err(Error::FinalityLock)
+            );
+            assert_eq!(escrow.refund(), Err(Error::NotExpired));
+            assert!(escrow.claimed_parts().is_empty());
+        }
+
+        #[ink::test]
+        fn test_claim_is_taker_only_without_public_window() {
+            let secrets = test_secrets(4);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 400, 400 + EXISTENTIAL_DEPOSIT);
+
+            // An observer holding a revealed secret cannot claim
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(AccountId::from([5u8; 32]));
+            assert_eq!(
+                escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                Err(Error::NotTaker)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(AccountId::from([2u8; 32]));
+            assert_eq!(escrow.claim_part(proofs[0].clone(), secrets[0], 0), Ok(()));
+        }
+
+        #[ink::test]
+        fn test_public_withdrawal_window() {
+            let secrets = test_secrets(4);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let contract = AccountId::from([0x42; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                400 + EXISTENTIAL_DEPOSIT,
+            );
+            let mut escrow = create_escrow_with(EscrowParams {
+                merkle_root,
+                timelocks: Timelocks::new(0, 500, 1000, u32::MAX),
+                ..test_params()
+            });
+            assert_eq!(escrow.get_stage_start(Stage::PublicWithdrawal), 500);
+
+            // Exclusive window: observer rejected
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(AccountId::from([5u8; 32]));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(499);
+            assert_eq!(
+                escrow.claim_part(proofs[0].clone(), secrets[0], 0),
+                Err(Error::NotTaker)
+            );
+
+            // Public window: anyone may claim, funds still go to the maker
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(500);
+            let maker_before = balance_of(AccountId::from([1u8; 32]));
+            assert_eq!(escrow.claim_part(proofs[0].clone(), secrets[0], 0), Ok(()));
+            assert_eq!(balance_of(AccountId::from([1u8; 32])) - maker_before, 100);
+        }
+
+        #[ink::test]
+        fn test_refund_is_taker_only_until_public_cancellation() {
+            let mut escrow = create_escrow_with(EscrowParams {
+                timelocks: Timelocks::new(0, 1000, 1000, 2000),
+                ..test_params()
+            });
+            assert_eq!(escrow.get_stage_start(Stage::PublicCancellation), 2000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(AccountId::from([5u8; 32]));
+
+            // Exclusive cancellation: only the taker
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1500);
+            assert_eq!(escrow.refund(), Err(Error::NotTaker));
+
+            // Public cancellation: anyone
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2000);
+            assert_eq!(escrow.refund(), Ok(()));
+            assert!(escrow.get_refunded());
+        }
+
+        #[ink::test]
+        fn test_refund_by_non_taker_during_exclusive_cancellation() {
+            let mut escrow = create_test_escrow();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(AccountId::from([1u8; 32]));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(u64::from(u32::MAX) - 1);
+
+            assert_eq!(escrow.refund(), Err(Error::NotTaker));
+        }
+
+        #[ink::test]
+        fn test_new_requires_safety_deposit() {
+            let params = EscrowParams { safety_deposit: 50, ..test_params() };
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(49);
+            let result = EscrowDst::new(params.clone());
+            assert_eq!(result.err(), Some(Error::InsufficientSafetyDeposit));
+
+            // The deposit alone does not fund the swap amount
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(50);
+            let escrow = EscrowDst::new(params.clone()).expect("escrow creation failed");
+            assert_eq!(escrow.get_safety_deposit(), 50);
+            assert_eq!(escrow.get_funded_amount(), 0);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(450);
+            let escrow = EscrowDst::new(params).expect("escrow creation failed");
+            assert_eq!(escrow.get_funded_amount(), 400);
+            assert!(escrow.is_funded());
+        }
+
+        #[ink::test]
+        fn test_withdrawals_pay_safety_deposit_to_caller() {
+            let maker = AccountId::from([1u8; 32]);
+            let taker = AccountId::from([2u8; 32]);
+            let resolver = AccountId::from([5u8; 32]);
+            let secrets = test_secrets(4);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let contract = AccountId::from([0x42; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                400 + 40 + EXISTENTIAL_DEPOSIT,
+            );
+            let mut escrow = create_escrow_with(EscrowParams {
+                merkle_root,
+                timelocks: Timelocks::new(0, 500, 1000, u32::MAX),
+                safety_deposit: 40,
+                ..test_params()
+            });
+
+            // The taker gets its own share back during the exclusive stage
+            let taker_before = balance_of(taker);
+            assert_eq!(escrow.claim_part(proofs[0].clone(), secrets[0], 0), Ok(()));
+            assert_eq!(balance_of(taker) - taker_before, 10);
+
+            // A resolver finishing the swap publicly earns the deposit, the maker
+            // still receives the full part amount
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(resolver);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(500);
+            let maker_before = balance_of(maker);
+            let resolver_before = balance_of(resolver);
+            assert_eq!(escrow.claim_part(proofs[1].clone(), secrets[1], 1), Ok(()));
+            assert_eq!(balance_of(maker) - maker_before, 100);
+            assert_eq!(balance_of(resolver) - resolver_before, 10);
+            assert_eq!(escrow.get_filled_amount(), 200);
+            assert_eq!(escrow.get_balance(), 200 + 20 + EXISTENTIAL_DEPOSIT);
+        }
+
+        #[ink::test]
+        fn test_public_cancellation_pays_remaining_safety_deposit() {
+            let taker = AccountId::from([2u8; 32]);
+            let resolver = AccountId::from([5u8; 32]);
+            let secrets = test_secrets(4);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let contract = AccountId::from([0x42; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                400 + 41 + EXISTENTIAL_DEPOSIT,
+            );
+            let mut escrow = create_escrow_with(EscrowParams {
+                merkle_root,
+                timelocks: Timelocks::new(0, 1000, 1000, 2000),
+                safety_deposit: 41,
+                ..test_params()
+            });
+            assert_eq!(escrow.claim_part(proofs[0].clone(), secrets[0], 0), Ok(()));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(resolver);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2000);
+            let taker_before = balance_of(taker);
+            let resolver_before = balance_of(resolver);
+            assert_eq!(escrow.refund(), Ok(()));
+            assert_eq!(balance_of(taker) - taker_before, 300);
+            assert_eq!(balance_of(resolver) - resolver_before, 31);
+            assert_eq!(escrow.get_balance(), EXISTENTIAL_DEPOSIT);
+        }
+
+        #[ink::test]
+        fn test_token_escrow_rejects_native_funding() {
+            let params = EscrowParams {
+                token: Some(AccountId::from([9u8; 32])),
+                safety_deposit: 10,
+                ..test_params()
+            };
+
+            // Only the native safety deposit may be sent on creation
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(11);
+            let result = EscrowDst::new(params.clone());
+            assert_eq!(result.err(), Some(Error::AssetMismatch));
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(10);
+            let mut escrow = EscrowDst::new(params).expect("escrow creation failed");
+            assert_eq!(escrow.get_token(), Some(AccountId::from([9u8; 32])));
+            assert_eq!(escrow.get_funded_amount(), 0);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            assert_eq!(escrow.fund(), Err(Error::AssetMismatch));
+
+            // Amounts are validated before any tokens are pulled
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            assert_eq!(escrow.fund_token(0), Err(Error::InvalidFundingAmount));
+            assert_eq!(escrow.fund_token(401), Err(Error::InvalidFundingAmount));
+        }
+
+        #[ink::test]
+        fn test_psp22_call_inputs() {
+            // The off-chain environment can't call contracts, so check the encoded
+            // inputs against the PSP22 selectors and argument order instead
+            let (from, to) = (AccountId::from([2u8; 32]), AccountId::from([1u8; 32]));
+
+            let input = EscrowDst::psp22_transfer(to, 100).encode();
+            let (selector, mut args) = input.split_at(4);
+            assert_eq!(selector, ink::selector_bytes!("PSP22::transfer"));
+            assert_eq!(
+                <(AccountId, Balance, Vec<u8>)>::decode(&mut args),
+                Ok((to, 100, Vec::new()))
+            );
+            assert!(args.is_empty());
+
+            let input = EscrowDst::psp22_transfer_from(from, to, 400).encode();
+            let (selector, mut args) = input.split_at(4);
+            assert_eq!(selector, ink::selector_bytes!("PSP22::transfer_from"));
+            assert_eq!(
+                <(AccountId, AccountId, Balance, Vec<u8>)>::decode(&mut args),
+                Ok((from, to, 400, Vec::new()))
+            );
+            assert!(args.is_empty());
+        }
+
+        #[ink::test]
+        fn test_native_escrow_rejects_token_funding() {
+            let mut escrow = create_unfunded_escrow();
+            assert_eq!(escrow.get_token(), None);
+            assert_eq!(escrow.fund_token(100), Err(Error::AssetMismatch));
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        #[ink::test]
+        fn test_pallet_asset_escrow() {
+            let maker = AccountId::from([1u8; 32]);
+            let taker = AccountId::from([2u8; 32]);
+            let contract = AccountId::from([0x42; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            let ledger = register_mock_assets(contract);
+            ledger.borrow_mut().balances.insert((7, taker), 400);
+            ledger.borrow_mut().approvals.insert((7, taker), 400);
+
+            let secrets = test_secrets(4);
+            let (merkle_root, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = EscrowDst::new(EscrowParams {
+                merkle_root,
+                asset_id: Some(7),
+                ..test_params()
+            })
+            .expect("escrow creation failed");
+            assert_eq!(escrow.get_asset_id(), Some(7));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(taker);
+
+            // Funding pulls the approved asset from the taker
+            assert_eq!(escrow.fund(), Err(Error::AssetMismatch));
+            assert_eq!(escrow.fund_token(400), Ok(()));
+            assert_eq!(ledger.borrow().balance(7, contract), 400);
+            assert_eq!(ledger.borrow().balance(7, taker), 0);
+
+            // Claims and refunds pay out the asset
+            assert_eq!(escrow.claim_part(proofs[0].clone(), secrets[0], 0), Ok(()));
+            assert_eq!(ledger.borrow().balance(7, maker), 100);
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1000);
+            assert_eq!(escrow.refund(), Ok(()));
+            assert_eq!(ledger.borrow().balance(7, taker), 300);
+            assert_eq!(ledger.borrow().balance(7, contract), 0);
+        }
+
+        #[cfg(feature = "pallet-assets")]
+        #[ink::test]
+        fn test_pallet_asset_funding_requires_approval() {
+            let taker = AccountId::from([2u8; 32]);
+            let ledger = register_mock_assets(AccountId::from([0x42; 32]));
+            ledger.borrow_mut().balances.insert((7, taker), 400);
+
+            let mut escrow = EscrowDst::new(EscrowParams {
+                asset_id: Some(7),
+                ..test_params()
+            })
+            .expect("escrow creation failed");
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(taker);
+            assert_eq!(escrow.fund_token(400), Err(Error::TransferFailed));
+
+            // A token and an asset cannot both be escrowed
+            let result = EscrowDst::new(EscrowParams {
+                token: Some(AccountId::from([9u8; 32])),
+                asset_id: Some(7),
+                ..test_params()
+            });
+            assert_eq!(result.err(), Some(Error::AssetMismatch));
+        }
+
+        #[cfg(not(feature = "pallet-assets"))]
+        #[ink::test]
+        fn test_pallet_asset_escrow_needs_feature() {
+            let result = EscrowDst::new(EscrowParams {
+                asset_id: Some(7),
+                ..test_params()
+            });
+            assert_eq!(result.err(), Some(Error::AssetMismatch));
+        }
+
+        #[ink::test]
+        fn test_sequential_claims_use_fixed_total() {
+            let maker = AccountId::from([1u8; 32]);
+            let amount = 1003;
+
+            for parts_count in 1..=5u32 {
+                let secrets = test_secrets(parts_count);
+                let (_, proofs) = build_merkle_tree(&secrets);
+
+                // Storage and existential deposits sit on top of the locked amount
+                let mut escrow = create_funded_escrow(&secrets, amount, amount + EXISTENTIAL_DEPOSIT);
+                let amount_per_part = amount / parts_count as u128;
+
+                let mut total_paid = 0;
+                for part_index in 0..parts_count {
+                    let index = part_index as usize;
+                    let maker_before = balance_of(maker);
+                    assert_eq!(
+                        escrow.claim_part(proofs[index].clone(), secrets[index], part_index),
+                        Ok(())
+                    );
+
+                    let paid = balance_of(maker) - maker_before;
+                    let expected = if part_index == parts_count - 1 {
+                        amount - amount_per_part * (parts_count as u128 - 1)
+                    } else {
+                        amount_per_part
+                    };
+                    assert_eq!(paid, expected);
+                    total_paid += paid;
+                }
+
+                assert_eq!(total_paid, amount);
+                assert_eq!(escrow.get_filled_amount(), amount);
+                assert_eq!(escrow.claimed_parts(), (0..parts_count).collect::<Vec<_>>());
+                assert_eq!(escrow.get_balance(), EXISTENTIAL_DEPOSIT);
+            }
+        }
+
+        #[ink::test]
+        fn test_last_part_gets_remainder() {
+            let maker = AccountId::from([1u8; 32]);
+            let secrets = test_secrets(3);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 10, 10 + EXISTENTIAL_DEPOSIT);
+
+            let mut payouts = Vec::new();
+            for part_index in 0..3u32 {
+                let index = part_index as usize;
+                let maker_before = balance_of(maker);
+                assert_eq!(
+                    escrow.claim_part(proofs[index].clone(), secrets[index], part_index),
+                    Ok(())
+                );
+                payouts.push(balance_of(maker) - maker_before);
+            }
+
+            assert_eq!(payouts, vec![3, 3, 4]);
+            assert_eq!(escrow.get_balance(), EXISTENTIAL_DEPOSIT);
+        }
+
+        #[ink::test]
+        fn test_refund_pays_unclaimed_locked_amount() {
+            let taker = AccountId::from([2u8; 32]);
+            let secrets = test_secrets(4);
+            let (_, proofs) = build_merkle_tree(&secrets);
+            let mut escrow = create_funded_escrow(&secrets, 1000, 1000 + EXISTENTIAL_DEPOSIT);
+
+            for part_index in 0..2u32 {
+                let index = part_index as usize;
+                assert_eq!(
+                    escrow.claim_part(proofs[index].clone(), secrets[index], part_index),
+                    Ok(())
+                );
+            }
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            let taker_before = balance_of(taker);
+            assert_eq!(escrow.refund(), Ok(()));
+
+            // Only the locked remainder is refunded, the extra balance stays put
+            assert_eq!(balance_of(taker) - taker_before, 500);
+            assert_eq!(escrow.get_balance(), EXISTENTIAL_DEPOSIT);
+        }
+
+        #[ink::test]
+        fn test_sorted_pairs_shared_vectors() {
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::SortedPairs,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+            assert_eq!(escrow.get_proof_mode(), ProofMode::SortedPairs);
+
+            for (part_index, (secret, leaf, proof)) in SORTED_PAIRS_PARTS.iter().enumerate() {
+                let part_index = part_index as u32;
+                let secret = hash_from_hex(secret);
+                let proof: Vec<Hash> = proof.iter().map(|hash| hash_from_hex(hash)).collect();
+
+                // Leaf is bound to the part index like abi.encodePacked(partIndex, secret)
+                assert_eq!(escrow.immutables.leaf(secret, part_index), hash_from_hex(leaf));
+                assert_eq!(escrow.claim_part(proof, secret, part_index), Ok(()));
+            }
+
+            assert_eq!(escrow.claimed_parts(), vec![0, 1, 2, 3]);
+            assert_eq!(escrow.get_filled_amount(), 1000);
+        }
+
+        /// Two-part sorted-pairs tree bound to chain 1000, factory `[0xfa; 32]`, maker
+        /// `[1; 32]` and nonce 7: domain separator, leaves of `[0x11; 32]` and
+        /// `[0x22; 32]`, root. From `test-vectors/merkle-domain-separated.json`, also
+        /// claimed against `EscrowSrc` by `Ethereum/scripts/check-merkle-vectors.js`
+        const DOMAIN_VECTORS: [&str; 4] = [
+            "0xb3fcf536c2716fe53542af2bca86e56720720a5008ddc41612c5328679373757",
+            "0xd694aafdbc78ff154939efa4774ce816b6412ee604e0df63b1f64e34b14fd913",
+            "0xd537e5ba2ce00a08191535f1902489542f5b63cddcba339adbdf9cb57403a3b9",
+            "0xd09b98048c9e04acf31cd54f0bc852dc8173d8534e93abe0ff34a8f288eec184",
+        ];
+
+        /// Leaf domain of `DOMAIN_VECTORS`
+        fn vector_leaf_domain() -> LeafDomain {
+            LeafDomain { chain_id: 1000, factory: AccountId::from([0xfa; 32]), nonce: 7 }
+        }
+
+        /// Create an escrow over the `DOMAIN_VECTORS` tree with `leaf_domain` at `contract`
+        fn deploy_domain_escrow(contract: AccountId, leaf_domain: LeafDomain) -> EscrowDst {
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                400 + EXISTENTIAL_DEPOSIT,
+            );
+            create_escrow_with(EscrowParams {
+                merkle_root: hash_from_hex(DOMAIN_VECTORS[3]),
+                parts_count: 2,
+                proof_mode: ProofMode::SortedPairs,
+                leaf_domain: Some(leaf_domain),
+                order_hash: Hash::from([0xab; 32]),
+                ..test_params()
+            })
+        }
+
+        #[ink::test]
+        fn test_domain_separated_leaves() {
+            let [domain_separator, leaf0, leaf1, _] = DOMAIN_VECTORS.map(hash_from_hex);
+            let mut escrow = deploy_domain_escrow(AccountId::from([0xee; 32]), vector_leaf_domain());
+            assert_eq!(escrow.get_domain_separator(), Some(domain_separator));
+            assert_eq!(escrow.immutables.leaf(Hash::from([0x11; 32]), 0), leaf0);
+
+            assert_eq!(escrow.claim_part(vec![leaf1], Hash::from([0x11; 32]), 0), Ok(()));
+            assert_eq!(escrow.claim_part(vec![leaf0], Hash::from([0x22; 32]), 1), Ok(()));
+            assert_eq!(escrow.get_filled_amount(), 400);
+        }
+
+        #[ink::test]
+        fn test_domain_separated_proof_is_bound_to_order() {
+            let [_, _, leaf1, _] = DOMAIN_VECTORS.map(hash_from_hex);
+
+            // The escrow address and order hash don't enter the leaves, so the tree can
+            // be built before either is known
+            let mut escrow = deploy_domain_escrow(AccountId::from([0xef; 32]), vector_leaf_domain());
+            assert_eq!(escrow.claim_part(vec![leaf1], Hash::from([0x11; 32]), 0), Ok(()));
+
+            // Another chain, factory or order nonce gives other leaves
+            for (i, leaf_domain) in [
+                LeafDomain { chain_id: 1001, ..vector_leaf_domain() },
+                LeafDomain { factory: AccountId::from([0xfb; 32]), ..vector_leaf_domain() },
+                LeafDomain { nonce: 8, ..vector_leaf_domain() },
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut escrow = deploy_domain_escrow(AccountId::from([0xf0 + i as u8; 32]), leaf_domain);
+                assert_eq!(
+                    escrow.claim_part(vec![leaf1], Hash::from([0x11; 32]), 0),
+                    Err(Error::InvalidProof)
+                );
+            }
+
+            // As does another maker
+            let other_maker = EscrowParams {
+                maker: AccountId::from([9u8; 32]),
+                leaf_domain: Some(vector_leaf_domain()),
+                proof_mode: ProofMode::SortedPairs,
+                ..test_params()
+            };
+            assert_ne!(other_maker.leaf(Hash::from([0x11; 32]), 0), hash_from_hex(DOMAIN_VECTORS[1]));
+
+            // Without domain separation the leaf is the plain one
+            let escrow = deploy_escrow_with(EscrowParams {
+                merkle_root: hash_from_hex(DOMAIN_VECTORS[3]),
+                parts_count: 2,
+                proof_mode: ProofMode::SortedPairs,
+                ..test_params()
+            });
+            assert_eq!(escrow.get_domain_separator(), None);
+            assert_ne!(escrow.immutables.leaf(Hash::from([0x11; 32]), 0), hash_from_hex(DOMAIN_VECTORS[1]));
+        }
+
+        /// Partial-fill tree of the same order over N = 2 parts, leaf `i` of secret
+        /// `[0x11 * (i + 1); 32]` bound to the escrow of fill `i`: separators and leaves
+        /// of fills 0 to 2, the node over leaves 0 and 1, root. From the `fills` of
+        /// `test-vectors/merkle-domain-separated.json`
+        const FILL_DOMAIN_VECTORS: [&str; 8] = [
+            "0xe5f3c9d61926b95bb3127c0566be81016cf98764529d436e02a82501d58225a2",
+            "0x30ddc9efd8a5c18159557f906ebd943a1e2d8fc26a48be9e0ff8dfa852ed13c9",
+            "0xb499e0e0b904abb4caebc7075d29f7a568a2d85aa37d490aa16766ecfa396620",
+            "0x407822846216e87e89014966195768fd901cf64911e955d7e7d1b6f9d18452f4",
+            "0x395a3be707ae10b13ca5c914fbc119e281a689fe1a1bc7d1845f2d9e9d38e4c8",
+            "0x742c2cd09d9b9b45bf3383db58824202289f09c93823132ad7b803e0d996eaa1",
+            "0xfcc675d01899469d750b52b611c058e697b73dd3d75fe8e1f8dab75805e95df1",
+            "0xcf7eda2d8892a6b074a4cdb826e65435cab78bdeaa2ef20259e2437631d3cf1c",
+        ];
+
+        /// Create an escrow of the `FILL_DOMAIN_VECTORS` order at `contract`, for the
+        /// fill at `fill_index` or the whole order
+        fn deploy_fill_domain_escrow(contract: AccountId, fill_index: Option<u32>) -> EscrowDst {
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                400 + EXISTENTIAL_DEPOSIT,
+            );
+            create_escrow_with(EscrowParams {
+                merkle_root: hash_from_hex(FILL_DOMAIN_VECTORS[7]),
+                parts_count: 2,
+                proof_mode: ProofMode::SortedPairs,
+                leaf_domain: Some(vector_leaf_domain()),
+                order_hash: Hash::from([0xab; 32]),
+                fill_index,
+                ..test_params()
+            })
+        }
+
+        #[ink::test]
+        fn test_domain_separated_proof_is_bound_to_escrow() {
+            let vectors = FILL_DOMAIN_VECTORS.map(hash_from_hex);
+            let (separators, leaves, node) = (&vectors[..3], &vectors[3..6], vectors[6]);
+            let secret = |i: u8| Hash::from([0x11 * (i + 1); 32]);
+
+            // Each fill escrow of the order has its own separator, none the whole order's
+            let mut fill = deploy_fill_domain_escrow(AccountId::from([0xe2; 32]), Some(2));
+            assert_eq!(fill.get_domain_separator(), Some(separators[2]));
+            assert_eq!(fill.immutables.leaf(secret(2), 2), leaves[2]);
+            assert!(!separators.contains(&hash_from_hex(DOMAIN_VECTORS[0])));
+
+            // Sibling escrows of the order reject fill 2's proof: the whole-order escrow,
+            // where secret 2 would complete the order, and the escrow of fill 1
+            let mut whole = deploy_fill_domain_escrow(AccountId::from([0xe3; 32]), None);
+            assert_eq!(
+                whole.claim_fill(400, vec![node], secret(2), 2),
+                Err(Error::InvalidProof)
+            );
+            let mut sibling = deploy_fill_domain_escrow(AccountId::from([0xe1; 32]), Some(1));
+            assert_eq!(
+                sibling.claim_fill(400, vec![node], secret(2), 1),
+                Err(Error::InvalidProof)
+            );
+
+            // While one tree serves every fill escrow of the order
+            assert_eq!(sibling.claim_fill(400, vec![leaves[0], leaves[2]], secret(1), 1), Ok(()));
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(AccountId::from([0xe2; 32]));
+            assert_eq!(fill.claim_fill(400, vec![node], secret(2), 2), Ok(()));
+        }
+
+        /// Order hashed by `ORDER_HASH_VECTORS`
+        fn vector_order() -> Order {
+            Order {
+                maker: AccountId::from([1u8; 32]),
+                taker: AccountId::from([2u8; 32]),
+                merkle_root: Hash::from([3u8; 32]),
+                parts_count: 4,
+                src_chain_id: 1,
+                dst_chain_id: 1000,
+                making_amount: 1_000_000_000_000_000_000,
+                taking_amount: 400,
+                token: Some(AccountId::from([6u8; 32])),
+                asset_id: None,
+                timelocks: Timelocks::new(60, 900, 1000, 2000),
+                time_unit: TimeUnit::Seconds,
+                safety_deposit: 25,
+                proof_mode: ProofMode::SortedPairs,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                bind_leaves: true,
+                nonce: 7,
+            }
+        }
+
+        /// Domain `ORDER_HASH_VECTORS` are hashed under: chain 1000, factory `[0xfa; 32]`
+        fn vector_domain() -> OrderDomain {
+            OrderDomain { chain_id: 1000, factory: AccountId::from([0xfa; 32]) }
+        }
+
+        /// `ORDER_TYPEHASH`, the domain separator of `vector_domain`, then the SCALE and
+        /// EIP-712 hashes of `vector_order`
+        const ORDER_HASH_VECTORS: [&str; 4] = [
+            "0x6f3f1e0c443a38a90ed9b4ffe84494983759285e263f4d45f20feba94785ee88",
+            "0x1a11e17c4a93a11e1cbe6618e32805368be7e48011d237b2d1b032900532e555",
+            "0x8e93a227f56bba60e72ff67d71cdb1b737538ab112c28d5563c2beadb32cc31a",
+            "0x34bbe19624f828b84dc7a064f140b6656ba2ce7c7b37d8fefaf36ce1afa984cc",
+        ];
 
-            // Check if expired
-            if self.env().block_timestamp() < self.expiry_timestamp {
-                ink::env::debug_println!("Escrow not expired");
-                return;
-            }
+        #[ink::test]
+        fn test_order_hash_vectors() {
+            let [type_hash, domain_separator, scale, eip712] = ORDER_HASH_VECTORS.map(hash_from_hex);
+            let mut output = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Keccak256>(Order::EIP712_TYPE.as_bytes(), &mut output);
+            assert_eq!(Hash::from(output), type_hash);
 
-            // Mark as refunded
-            self.refunded = true;
+            let (order, domain) = (vector_order(), vector_domain());
+            assert_eq!(domain.separator(), domain_separator);
+            assert_eq!(order.hash(&domain, OrderEncoding::Scale), scale);
+            assert_eq!(order.hash(&domain, OrderEncoding::Eip712), eip712);
 
-            // Transfer remaining balance to taker
-            let remaining_balance = self.env().balance();
-            if remaining_balance > 0 && self.env().transfer(self.taker, remaining_balance).is_err() {
-                ink::env::debug_println!("Transfer failed");
-                return;
+            // Every field and the domain are covered by the hash
+            let base = vector_order();
+            let others = [
+                Order { maker: AccountId::from([9u8; 32]), ..base.clone() },
+                Order { taker: AccountId::from([9u8; 32]), ..base.clone() },
+                Order { merkle_root: Hash::from([9u8; 32]), ..base.clone() },
+                Order { parts_count: 5, ..base.clone() },
+                Order { src_chain_id: 2, ..base.clone() },
+                Order { dst_chain_id: 1001, ..base.clone() },
+                Order { making_amount: 1, ..base.clone() },
+                Order { taking_amount: 401, ..base.clone() },
+                Order { token: None, ..base.clone() },
+                Order { token: Some(AccountId::from([0u8; 32])), ..base.clone() },
+                Order { token: None, asset_id: Some(0), ..base.clone() },
+                Order { timelocks: Timelocks::new(60, 900, 1000, 2001), ..base.clone() },
+                Order { time_unit: TimeUnit::Milliseconds, ..base.clone() },
+                Order { safety_deposit: 26, ..base.clone() },
+                Order { proof_mode: ProofMode::Positional, ..base.clone() },
+                Order { hash_algorithm: HashAlgorithm::Sha256, ..base.clone() },
+                Order { bind_leaves: false, ..base.clone() },
+                Order { nonce: 8, ..base.clone() },
+            ];
+            for other in &others {
+                assert_ne!(other.hash(&domain, OrderEncoding::Scale), scale);
+                assert_ne!(other.hash(&domain, OrderEncoding::Eip712), eip712);
+            }
+            for domain in [
+                OrderDomain { chain_id: 1001, ..vector_domain() },
+                OrderDomain { factory: AccountId::from([0xfb; 32]), ..vector_domain() },
+            ] {
+                assert_ne!(order.hash(&domain, OrderEncoding::Scale), scale);
+                assert_ne!(order.hash(&domain, OrderEncoding::Eip712), eip712);
             }
+        }
 
-            // Emit Refunded event
-            self.env().emit_event(Refunded {
-                taker: self.taker,
-                amount: remaining_balance,
-            });
+        #[ink::test]
+        fn test_escrow_stores_order_hash() {
+            let order_hash = vector_order().hash(&vector_domain(), OrderEncoding::Eip712);
+            let escrow = deploy_escrow_with(EscrowParams { order_hash, ..test_params() });
+            assert_eq!(escrow.get_order_hash(), order_hash);
         }
 
-        /// Get maker address
-        #[ink(message)]
-        pub fn get_maker(&self) -> AccountId {
-            self.maker
+        #[ink::test]
+        fn test_sorted_pairs_rejects_wrong_index() {
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::SortedPairs,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+
+            // Secret and proof of part 0 presented as part 1
+            let (secret, _, proof) = SORTED_PAIRS_PARTS[0];
+            let proof: Vec<Hash> = proof.iter().map(|hash| hash_from_hex(hash)).collect();
+            assert_eq!(
+                escrow.claim_part(proof, hash_from_hex(secret), 1),
+                Err(Error::InvalidProof)
+            );
         }
 
-        /// Get taker address
-        #[ink(message)]
-        pub fn get_taker(&self) -> AccountId {
-            self.taker
+        #[ink::test]
+        fn test_positional_mode_rejects_sorted_pairs_proofs() {
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::Positional,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+
+            let (secret, _, proof) = SORTED_PAIRS_PARTS[0];
+            let proof: Vec<Hash> = proof.iter().map(|hash| hash_from_hex(hash)).collect();
+            assert_eq!(
+                escrow.claim_part(proof, hash_from_hex(secret), 0),
+                Err(Error::InvalidProof)
+            );
         }
 
-        /// Get merkle root
-        #[ink(message)]
-        pub fn get_merkle_root(&self) -> Hash {
-            self.merkle_root
+        /// Leaves `hash([0x11; 32])` and `hash([0x22; 32])` and the two-leaf root
+        /// `hash(leaf0 ++ leaf1)` for each algorithm, computed independently of ink!
+        const HASH_ALGORITHM_VECTORS: [(HashAlgorithm, &str, &str, &str); 3] = [
+            (
+                HashAlgorithm::Keccak256,
+                "0xb569321de72d0af89c2fb48a484de3fc9343f31600ae1f3e13d633cb48cbf816",
+                "0xc4bd59e1394781d1c7bf20a2c0b30c2acc9fbdd52dc5e0d76917de4034ebdf59",
+                "0x41f634de17e88be92f0d8a089d5c15656df0a848598f828fce5e58978b35e10a",
+            ),
+            (
+                HashAlgorithm::Sha256,
+                "0x02d449a31fbb267c8f352e9968a79e3e5fc95c1bbeaa502fd6454ebde5a4bedc",
+                "0x9f72ea0cf49536e3c66c787f705186df9a4378083753ae9536d65b3ad7fcddc4",
+                "0x4aa9c7fb082fdd4e0228c3f7447d26c928b596ce0acb554900ac7f3fdbfe9dd8",
+            ),
+            (
+                HashAlgorithm::Blake2x256,
+                "0xd4ffaeeac45aa41825e0bc3f875570af061acbf0b950ad752ff0f9463fe13ad5",
+                "0xe2a94e18647fe0c6283a31e40c46ae1cc5f0867650f6834e4f01e34284adc9c7",
+                "0x1c7133f520cb8760e66fd596362ce126221e7e92175e838e69c134c458de1a2d",
+            ),
+        ];
+
+        /// Create a funded escrow at an account derived from `merkle_root`
+        fn deploy_escrow_with(params: EscrowParams) -> EscrowDst {
+            let contract = AccountId::from(<[u8; 32]>::from(params.merkle_root));
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                params.amount + EXISTENTIAL_DEPOSIT,
+            );
+            create_escrow_with(params)
         }
 
-        /// Get parts count
-        #[ink(message)]
-        pub fn get_parts_count(&self) -> u32 {
-            self.parts_count
+        #[ink::test]
+        fn test_hash_algorithm_vectors() {
+            let secrets = [Hash::from([0x11; 32]), Hash::from([0x22; 32])];
+            for (hash_algorithm, leaf0, leaf1, root) in HASH_ALGORITHM_VECTORS {
+                let (leaf0, leaf1) = (hash_from_hex(leaf0), hash_from_hex(leaf1));
+                let mut escrow = deploy_escrow_with(EscrowParams {
+                    merkle_root: hash_from_hex(root),
+                    parts_count: 2,
+                    hash_algorithm,
+                    ..test_params()
+                });
+                assert_eq!(escrow.get_hash_algorithm(), hash_algorithm);
+                assert_eq!(escrow.immutables.leaf(secrets[0], 0), leaf0);
+                assert_eq!(EscrowDst::hash_pair(&escrow.immutables, leaf0, leaf1), hash_from_hex(root));
+
+                assert_eq!(escrow.claim_part(vec![leaf1], secrets[0], 0), Ok(()));
+                assert_eq!(escrow.claim_part(vec![leaf0], secrets[1], 1), Ok(()));
+                assert_eq!(escrow.get_filled_amount(), 400);
+            }
         }
 
-        /// Get expiry timestamp
-        #[ink(message)]
-        pub fn get_expiry_timestamp(&self) -> u64 {
-            self.expiry_timestamp
+        #[ink::test]
+        fn test_hashlock_uses_hash_algorithm() {
+            let secret = Hash::from([0x11; 32]);
+
+            // A Keccak-256 hashlock does not open a SHA-256 escrow
+            let (_, keccak_hashlock, _, _) = HASH_ALGORITHM_VECTORS[0];
+            let mut escrow = deploy_escrow_with(EscrowParams {
+                merkle_root: hash_from_hex(keccak_hashlock),
+                parts_count: 1,
+                proof_mode: ProofMode::Hashlock,
+                hash_algorithm: HashAlgorithm::Sha256,
+                ..test_params()
+            });
+            assert_eq!(escrow.withdraw(secret), Err(Error::InvalidProof));
+
+            for (hash_algorithm, hashlock, _, _) in HASH_ALGORITHM_VECTORS {
+                let mut escrow = deploy_escrow_with(EscrowParams {
+                    merkle_root: hash_from_hex(hashlock),
+                    parts_count: 1,
+                    proof_mode: ProofMode::Hashlock,
+                    hash_algorithm,
+                    ..test_params()
+                });
+                assert_eq!(escrow.withdraw(secret), Ok(()));
+            }
         }
 
-        /// Get parts claimed
-        #[ink(message)]
-        pub fn get_parts_claimed(&self) -> u32 {
-            self.parts_claimed
+        /// `(part_index, secret)` pairs and leaves of the shared vectors
+        fn sorted_pairs_parts(indices: &[u32]) -> (Vec<(u32, Hash)>, Vec<Hash>) {
+            indices
+                .iter()
+                .map(|&index| {
+                    let (secret, leaf, _) = SORTED_PAIRS_PARTS[index as usize];
+                    ((index, hash_from_hex(secret)), hash_from_hex(leaf))
+                })
+                .unzip()
         }
 
-        /// Get refunded status
-        #[ink(message)]
-        pub fn get_refunded(&self) -> bool {
-            self.refunded
+        #[ink::test]
+        fn test_claim_parts_with_multiproof() {
+            let maker = AccountId::from([1u8; 32]);
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::SortedPairs,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+            let maker_before = balance_of(maker);
+            let events_before = ink::env::test::recorded_events().count();
+
+            // Parts 0 and 2: their siblings come from the proof, then the two
+            // computed nodes are combined into the root
+            let (parts, _) = sorted_pairs_parts(&[0, 2]);
+            let multiproof = MultiProof {
+                proof: vec![Hash::default(); 2],
+                proof_flags: vec![false, false, true],
+            };
+            assert_eq!(
+                escrow.claim_parts(parts.clone(), multiproof),
+                Err(Error::InvalidProof)
+            );
+
+            let (_, siblings) = sorted_pairs_parts(&[1, 3]);
+            let multiproof = MultiProof {
+                proof: siblings,
+                proof_flags: vec![false, false, true],
+            };
+            assert_eq!(escrow.claim_parts(parts, multiproof), Ok(()));
+            assert_eq!(balance_of(maker) - maker_before, 500);
+            assert_eq!(escrow.claimed_parts(), vec![0, 2]);
+            assert_eq!(escrow.get_filled_amount(), 500);
+
+            // One PartClaimed event per part
+            let events = ink::env::test::recorded_events().count();
+            assert_eq!(events - events_before, 2);
+
+            // Remaining parts, including the last part's remainder
+            let (parts, _) = sorted_pairs_parts(&[1, 3]);
+            let (_, siblings) = sorted_pairs_parts(&[0, 2]);
+            let multiproof = MultiProof {
+                proof: siblings,
+                proof_flags: vec![false, false, true],
+            };
+            assert_eq!(escrow.claim_parts(parts, multiproof), Ok(()));
+            assert_eq!(escrow.claimed_parts(), vec![0, 1, 2, 3]);
+            assert_eq!(escrow.get_filled_amount(), 1000);
         }
 
-        /// Get current balance
-        #[ink(message)]
-        pub fn get_balance(&self) -> Balance {
-            self.env().balance()
+        #[ink::test]
+        fn test_claim_parts_all_leaves_without_proof() {
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::SortedPairs,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+
+            let (parts, _) = sorted_pairs_parts(&[0, 1, 2, 3]);
+            let multiproof = MultiProof {
+                proof: vec![],
+                proof_flags: vec![true, true, true],
+            };
+            assert_eq!(escrow.claim_parts(parts, multiproof), Ok(()));
+            assert_eq!(escrow.claimed_parts(), vec![0, 1, 2, 3]);
+            assert_eq!(escrow.get_filled_amount(), 1000);
         }
 
-        /// Verify Merkle proof using Keccak-256
-        fn verify_merkle_proof(&self, proof: Vec<Hash>, secret: Hash, part_index: u32) -> bool {
-            let mut current_hash = self.hash_secret(secret);
-            
-            for (i, proof_hash) in proof.iter().enumerate() {
-                let bit = (part_index >> i) & 1;
-                if bit == 0 {
-                    // Current hash is left child
-                    current_hash = self.hash_pair(current_hash, *proof_hash);
-                } else {
-                    // Current hash is right child
-                    current_hash = self.hash_pair(*proof_hash, current_hash);
+        #[ink::test]
+        fn test_claim_parts_multiproof_savings() {
+            use ink::scale::Encode;
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let escrow = deploy_funded_escrow(root, 4, ProofMode::SortedPairs, 1000, 1000 + EXISTENTIAL_DEPOSIT);
+            let immutables = &escrow.immutables;
+
+            // Pair hashes, proof hashes and encoded arguments of N single claims
+            // against one batch: each single proof climbs the whole tree, while the
+            // multiproof hashes every shared node once and ships only outside siblings
+            let batches = [
+                (vec![0, 2], vec![1, 3], vec![false, false, true], (4, 4, 202), (3, 2, 142)),
+                (vec![0, 1, 2, 3], vec![], vec![true, true, true], (8, 8, 404), (3, 0, 150)),
+            ];
+            for (indices, sibling_indices, proof_flags, singles, batch) in batches {
+                let (parts, leaves) = sorted_pairs_parts(&indices);
+                let (_, proof) = sorted_pairs_parts(&sibling_indices);
+
+                let (mut pair_hashes, mut proof_hashes, mut call_data) = (0, 0, 0);
+                for &(part_index, secret) in &parts {
+                    let proof: Vec<Hash> = SORTED_PAIRS_PARTS[part_index as usize]
+                        .2
+                        .iter()
+                        .map(|hash| hash_from_hex(hash))
+                        .collect();
+                    // One pair hash per proof hash
+                    pair_hashes += proof.len();
+                    proof_hashes += proof.len();
+                    call_data += (&proof, secret, part_index).encoded_size();
+                    assert!(escrow.verify_merkle_proof(immutables, proof, secret, part_index));
                 }
-            }
+                assert_eq!((pair_hashes, proof_hashes, call_data), singles);
 
-            current_hash == self.merkle_root
+                // One pair hash per flag
+                let multiproof = MultiProof { proof, proof_flags };
+                let batch_call_data = (&parts, &multiproof).encoded_size();
+                assert_eq!(
+                    (multiproof.proof_flags.len(), multiproof.proof.len(), batch_call_data),
+                    batch
+                );
+                assert_eq!(escrow.process_multiproof(immutables, &leaves, &multiproof), Some(root));
+            }
         }
 
-        /// Hash a secret using Keccak-256
-        fn hash_secret(&self, secret: Hash) -> Hash {
-            use ink::env::hash::Keccak256;
-            let mut output = [0u8; 32];
-            ink::env::hash_bytes::<Keccak256>(secret.as_ref(), &mut output);
-            Hash::from(output)
-        }
+        #[ink::test]
+        fn test_claim_parts_rejects_invalid_batches() {
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::SortedPairs,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+            let all_leaves = MultiProof {
+                proof: vec![],
+                proof_flags: vec![true, true, true],
+            };
 
-        /// Hash a pair of hashes using Keccak-256
-        fn hash_pair(&self, left: Hash, right: Hash) -> Hash {
-            use ink::env::hash::Keccak256;
-            let mut input = Vec::new();
-            input.extend_from_slice(left.as_ref());
-            input.extend_from_slice(right.as_ref());
-            
-            let mut output = [0u8; 32];
-            ink::env::hash_bytes::<Keccak256>(&input, &mut output);
-            Hash::from(output)
-        }
-    }
+            assert_eq!(
+                escrow.claim_parts(vec![], MultiProof::default()),
+                Err(Error::InvalidPartIndex)
+            );
 
-    /// Unit tests
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+            // The same part twice in one batch
+            let (parts, _) = sorted_pairs_parts(&[0, 1, 2, 2]);
+            assert_eq!(
+                escrow.claim_parts(parts, all_leaves.clone()),
+                Err(Error::AlreadyClaimed)
+            );
 
-        fn create_test_escrow() -> EscrowDst {
-            let maker = AccountId::from([1u8; 32]);
-            let taker = AccountId::from([2u8; 32]);
-            let merkle_root = Hash::from([3u8; 32]);
-            let parts_count = 4;
-            let expiry_timestamp = 1000;
+            // Flags must account for every leaf and proof hash
+            let (parts, _) = sorted_pairs_parts(&[0, 1, 2, 3]);
+            let truncated = MultiProof {
+                proof: vec![],
+                proof_flags: vec![true, true],
+            };
+            assert_eq!(
+                escrow.claim_parts(parts.clone(), truncated),
+                Err(Error::InvalidProof)
+            );
 
-            EscrowDst::new(maker, taker, merkle_root, parts_count, expiry_timestamp)
+            // A part claimed on its own can't be claimed again in a batch
+            let (secret, _, proof) = SORTED_PAIRS_PARTS[3];
+            let proof: Vec<Hash> = proof.iter().map(|hash| hash_from_hex(hash)).collect();
+            assert_eq!(escrow.claim_part(proof, hash_from_hex(secret), 3), Ok(()));
+            assert_eq!(
+                escrow.claim_parts(parts, all_leaves),
+                Err(Error::AlreadyClaimed)
+            );
+            assert_eq!(escrow.claimed_parts(), vec![3]);
         }
 
         #[ink::test]
-        fn test_new() {
-            let escrow = create_test_escrow();
-            
-            assert_eq!(escrow.get_maker(), AccountId::from([1u8; 32]));
-            assert_eq!(escrow.get_taker(), AccountId::from([2u8; 32]));
-            assert_eq!(escrow.get_merkle_root(), Hash::from([3u8; 32]));
-            assert_eq!(escrow.get_parts_count(), 4);
-            assert_eq!(escrow.get_expiry_timestamp(), 1000);
-            assert_eq!(escrow.get_parts_claimed(), 0);
-            assert_eq!(escrow.get_refunded(), false);
+        fn test_claim_parts_requires_sorted_pairs() {
+            let root = hash_from_hex(SORTED_PAIRS_ROOT);
+            let mut escrow = deploy_funded_escrow(
+                root,
+                4,
+                ProofMode::Positional,
+                1000,
+                1000 + EXISTENTIAL_DEPOSIT,
+            );
+
+            let (parts, _) = sorted_pairs_parts(&[0, 1, 2, 3]);
+            let multiproof = MultiProof {
+                proof: vec![],
+                proof_flags: vec![true, true, true],
+            };
+            assert_eq!(
+                escrow.claim_parts(parts, multiproof),
+                Err(Error::UnsupportedProofMode)
+            );
         }
 
         #[ink::test]
-        fn test_invalid_part_index() {
-            let mut escrow = create_test_escrow();
-            escrow.claim_part(vec![], Hash::from([1u8; 32]), 5);
-            // Should not change parts_claimed due to invalid index
-            assert_eq!(escrow.get_parts_claimed(), 0);
+        fn test_merkle_proof_verification() {
+            let escrow = create_test_escrow();
+            
+            // Test with empty proof and invalid secret
+            let result = escrow.verify_merkle_proof(&escrow.immutables, vec![], Hash::from([1u8; 32]), 0);
+            assert!(!result);
         }
 
         #[ink::test]
-        fn test_part_already_claimed() {
+        fn test_claim_without_proof_rejected() {
             let mut escrow = create_test_escrow();
             
-            // Try to claim part 2 before part 0
-            escrow.claim_part(vec![], Hash::from([1u8; 32]), 2);
-            // Should not change parts_claimed due to out of order
-            assert_eq!(escrow.get_parts_claimed(), 0);
+            // A part of a multi-part tree can't be claimed without its proof
+            assert_eq!(
+                escrow.claim_part(vec![], Hash::from([1u8; 32]), 1),
+                Err(Error::InvalidProof)
+            );
+            // Should not claim anything due to the invalid proof
+            assert!(escrow.claimed_parts().is_empty());
         }
+    }
 
-        #[ink::test]
-        fn test_escrow_expired() {
-            let maker = AccountId::from([1u8; 32]);
-            let taker = AccountId::from([2u8; 32]);
-            let merkle_root = Hash::from([3u8; 32]);
-            let parts_count = 4;
-            let expiry_timestamp = 1000;
+    /// Unit tests of the `immutables-hash` build
+    #[cfg(all(test, feature = "immutables-hash"))]
+    mod immutables_hash_tests {
+        use super::*;
+        use ink::storage::traits::Storable;
 
-            let mut escrow = EscrowDst::new(maker, taker, merkle_root, parts_count, expiry_timestamp);
-            
-            // Set block timestamp to after expiry
-            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
-            
-            escrow.claim_part(vec![], Hash::from([1u8; 32]), 0);
-            // Should not change parts_claimed due to expiry
-            assert_eq!(escrow.get_parts_claimed(), 0);
+        /// Minimum balance the off-chain environment keeps on accounts
+        const EXISTENTIAL_DEPOSIT: Balance = 1_000_000;
+
+        fn test_secret() -> Hash {
+            Hash::from([0x11; 32])
+        }
+
+        /// Single-part hashlock over `test_secret`
+        fn test_params() -> Immutables {
+            let mut hashlock = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Keccak256>(test_secret().as_ref(), &mut hashlock);
+            EscrowParams {
+                maker: AccountId::from([1u8; 32]),
+                taker: AccountId::from([2u8; 32]),
+                merkle_root: Hash::from(hashlock),
+                parts_count: 1,
+                // Taker-only claims until 1000, taker-only refunds after
+                timelocks: Timelocks::new(0, 1000, 1000, u32::MAX),
+                time_unit: TimeUnit::Milliseconds,
+                token: None,
+                asset_id: None,
+                amount: 400,
+                safety_deposit: 0,
+                proof_mode: ProofMode::Hashlock,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            }
+        }
+
+        /// Create the funded escrow at its own account, with the taker as caller
+        fn create_test_escrow() -> EscrowDst {
+            let params = test_params();
+            let contract = AccountId::from([0xee; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract,
+                params.amount + EXISTENTIAL_DEPOSIT,
+            );
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(params.amount);
+            let escrow = EscrowDst::new(params.clone()).expect("escrow creation failed");
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(params.taker);
+            escrow
         }
 
         #[ink::test]
-        fn test_refund_before_expiry() {
-            let mut escrow = create_test_escrow();
-            
-            // Set block timestamp to before expiry
-            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(999);
-            
-            escrow.refund();
-            // Should not refund due to not expired
-            assert_eq!(escrow.get_refunded(), false);
+        fn test_new_stores_immutables_hash() {
+            let escrow = create_test_escrow();
+            assert_eq!(escrow.get_immutables_hash(), test_params().hash());
+            assert_eq!(escrow.is_funded(test_params()), Ok(true));
+            assert_eq!(escrow.current_stage(test_params()), Ok(Stage::ExclusiveWithdrawal));
         }
 
         #[ink::test]
-        fn test_refund_after_expiry() {
-            let mut escrow = create_test_escrow();
-            
-            // Set block timestamp to after expiry
-            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
-            
-            escrow.refund();
-            // Should refund after expiry
-            assert_eq!(escrow.get_refunded(), true);
+        fn test_dst_created_carries_immutables() {
+            let escrow = create_test_escrow();
+
+            // Callers rebuild the immutables the messages take from the creation event
+            let event = ink::env::test::recorded_events().next().expect("no DstCreated event");
+            let created = <DstCreated as ink::scale::Decode>::decode(&mut &event.data[..])
+                .expect("undecodable DstCreated event");
+            assert_eq!(created.immutables, test_params());
+            assert_eq!(created.immutables.hash(), escrow.get_immutables_hash());
         }
 
         #[ink::test]
-        fn test_double_refund() {
+        fn test_messages_check_immutables() {
             let mut escrow = create_test_escrow();
-            
-            // Set block timestamp to after expiry
-            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
-            
-            // First refund should succeed
-            escrow.refund();
-            assert_eq!(escrow.get_refunded(), true);
-            
-            // Second refund should not change state
-            escrow.refund();
-            assert_eq!(escrow.get_refunded(), true);
+
+            // Any field differing from creation is rejected, whatever the secret
+            let forged = [
+                EscrowParams { maker: AccountId::from([9u8; 32]), ..test_params() },
+                EscrowParams { amount: 1, ..test_params() },
+                EscrowParams { timelocks: Timelocks::new(0, 0, 0, 1), ..test_params() },
+            ];
+            for immutables in forged {
+                assert_eq!(escrow.withdraw(immutables.clone(), test_secret()), Err(Error::InvalidImmutables));
+                assert_eq!(escrow.refund(immutables.clone()), Err(Error::InvalidImmutables));
+                assert_eq!(escrow.claimed_parts(immutables), Err(Error::InvalidImmutables));
+            }
+            assert_eq!(escrow.get_filled_amount(), 0);
+
+            assert_eq!(escrow.withdraw(test_params(), test_secret()), Ok(()));
+            assert_eq!(escrow.get_filled_amount(), 400);
+            assert_eq!(escrow.get_revealed_secrets(test_params()), Ok(vec![(0, test_secret())]));
         }
 
         #[ink::test]
-        fn test_claim_after_refund() {
+        fn test_refund_with_immutables() {
             let mut escrow = create_test_escrow();
-            
-            // Set block timestamp to after expiry
+            assert_eq!(escrow.refund(test_params()), Err(Error::NotExpired));
+
             ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
-            
-            // Refund the escrow
-            escrow.refund();
-            assert_eq!(escrow.get_refunded(), true);
-            
-            // Try to claim after refund
-            escrow.claim_part(vec![], Hash::from([1u8; 32]), 0);
-            // Should not change parts_claimed due to refunded state
-            assert_eq!(escrow.get_parts_claimed(), 0);
+            assert_eq!(escrow.refund(test_params()), Ok(()));
+            assert!(escrow.get_refunded());
         }
 
         #[ink::test]
-        fn test_merkle_proof_verification() {
+        fn test_storage_footprint() {
             let escrow = create_test_escrow();
-            
-            // Test with empty proof and invalid secret
-            let result = escrow.verify_merkle_proof(vec![], Hash::from([1u8; 32]), 0);
-            assert_eq!(result, false);
-        }
 
-        #[ink::test]
-        fn test_sequential_claim_validation() {
-            let mut escrow = create_test_escrow();
-            
-            // Try to claim part 1 before part 0
-            escrow.claim_part(vec![], Hash::from([1u8; 32]), 1);
-            // Should not change parts_claimed due to out of order
-            assert_eq!(escrow.get_parts_claimed(), 0);
+            // The 32-byte hash replaces the creation parameters in the root storage cell,
+            // next to the deployment time and block, funded, filled and paid-out amounts
+            // and refunded flag
+            let stored = Storable::encoded_size(&escrow);
+            assert_eq!(stored, 93);
+            let mutable_state: (u64, BlockNumber, Balance, Balance, Balance, bool) = Default::default();
+            assert_eq!(stored, 32 + ink::scale::Encode::encoded_size(&mutable_state));
+
+            // The original seven-field escrow stored maker, taker, merkle root, parts
+            // count, expiry, claimed-parts count and refunded flag: even with timelocks,
+            // funding and fill tracking on top, the hash-only layout stays below it
+            #[ink::scale_derive(Encode)]
+            struct BaselineEscrow {
+                maker: AccountId,
+                taker: AccountId,
+                merkle_root: Hash,
+                parts_count: u32,
+                expiry_timestamp: u64,
+                parts_claimed: u32,
+                refunded: bool,
+            }
+            let baseline = BaselineEscrow {
+                maker: AccountId::from([1u8; 32]),
+                taker: AccountId::from([2u8; 32]),
+                merkle_root: Hash::from([3u8; 32]),
+                parts_count: 1,
+                expiry_timestamp: 0,
+                parts_claimed: 0,
+                refunded: false,
+            };
+            let baseline_size = ink::scale::Encode::encoded_size(&baseline);
+            assert_eq!(baseline_size, 113);
+            assert!(stored < baseline_size);
         }
     }
 
     /// E2E tests
-    #[cfg(all(test, feature = "e2e-tests"))]
+    #[cfg(all(
+        test,
+        feature = "e2e-tests",
+        not(feature = "immutables-hash"),
+        not(feature = "pallet-assets")
+    ))]
     mod e2e_tests {
         use super::*;
         use ink_e2e::ContractsBackend;
@@ -440,17 +4057,33 @@ mod escrow_dst {
         #[ink_e2e::test]
         async fn test_escrow_creation(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
             // Given
-            let maker = ink_e2e::alice().account_id();
-            let taker = ink_e2e::bob().account_id();
-            let merkle_root = Hash::from([3u8; 32]);
-            let parts_count = 4;
-            let expiry_timestamp = 1000;
+            let maker = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let taker = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let amount = 1_000_000_000;
+            let params = EscrowParams {
+                maker,
+                taker,
+                merkle_root: Hash::from([3u8; 32]),
+                parts_count: 4,
+                timelocks: Timelocks::new(0, 1000, 1000, u32::MAX),
+                time_unit: TimeUnit::Milliseconds,
+                token: None,
+                asset_id: None,
+                amount,
+                safety_deposit: 0,
+                proof_mode: ProofMode::Positional,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            };
 
-            let mut constructor = EscrowDstRef::new(maker, taker, merkle_root, parts_count, expiry_timestamp);
+            let mut constructor = EscrowDstRef::new(params);
 
             // When
             let contract = client
                 .instantiate("escrow_dst", &ink_e2e::alice(), &mut constructor)
+                .value(amount)
                 .submit()
                 .await
                 .expect("instantiate failed");
@@ -463,26 +4096,278 @@ mod escrow_dst {
             
             assert_eq!(maker_result, maker);
 
+            let is_funded = call_builder.is_funded();
+            let result = client.call(&ink_e2e::alice(), &is_funded).dry_run().await?;
+            assert!(result.return_value());
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn test_claim_rejected_with_reason(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let maker = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let taker = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let mut constructor = EscrowDstRef::new(EscrowParams {
+                maker,
+                taker,
+                merkle_root: Hash::from([3u8; 32]),
+                parts_count: 4,
+                timelocks: Timelocks::new(0, u32::MAX, u32::MAX, u32::MAX),
+                time_unit: TimeUnit::Milliseconds,
+                token: None,
+                asset_id: None,
+                amount: 0,
+                safety_deposit: 0,
+                proof_mode: ProofMode::Positional,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            });
+            let contract = client
+                .instantiate("escrow_dst", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut call_builder = contract.call_builder::<EscrowDst>();
+
+            // When
+            let claim = call_builder.claim_part(vec![], Hash::from([1u8; 32]), 5);
+            let result = client.call(&ink_e2e::bob(), &claim).dry_run().await?;
+
+            // Then
+            assert_eq!(result.return_value(), Err(Error::InvalidPartIndex));
+
             Ok(())
         }
 
         #[ink_e2e::test]
-        async fn test_full_fill_scenario(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // This test would simulate a full-fill scenario where all parts are claimed
-            // Implementation would require setting up proper Merkle proofs
+        async fn test_claim_parts_weight(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use super::tests::{hash_from_hex, SORTED_PAIRS_PARTS, SORTED_PAIRS_ROOT};
+
+            // Given
+            let maker = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let taker = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let amount = 1_000_000_000;
+            let mut constructor = EscrowDstRef::new(EscrowParams {
+                maker,
+                taker,
+                merkle_root: hash_from_hex(SORTED_PAIRS_ROOT),
+                parts_count: 4,
+                timelocks: Timelocks::new(0, u32::MAX, u32::MAX, u32::MAX),
+                time_unit: TimeUnit::Milliseconds,
+                token: None,
+                asset_id: None,
+                amount,
+                safety_deposit: 0,
+                proof_mode: ProofMode::SortedPairs,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            });
+            let contract = client
+                .instantiate("escrow_dst", &ink_e2e::bob(), &mut constructor)
+                .value(amount)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut call_builder = contract.call_builder::<EscrowDst>();
+
+            // When: four single claims, each dry-run against the same fresh state
+            let mut single_claims_ref_time = 0;
+            let mut parts = Vec::new();
+            for (part_index, (secret, _, proof)) in SORTED_PAIRS_PARTS.iter().enumerate() {
+                let part_index = part_index as u32;
+                let secret = hash_from_hex(secret);
+                let proof = proof.iter().map(|hash| hash_from_hex(hash)).collect();
+                let claim = call_builder.claim_part(proof, secret, part_index);
+                let result = client.call(&ink_e2e::bob(), &claim).dry_run().await?;
+                single_claims_ref_time += result.exec_result.gas_consumed.ref_time();
+                assert_eq!(result.return_value(), Ok(()));
+                parts.push((part_index, secret));
+            }
+
+            let multiproof = MultiProof {
+                proof: vec![],
+                proof_flags: vec![true, true, true],
+            };
+            let claim = call_builder.claim_parts(parts, multiproof);
+            let result = client.call(&ink_e2e::bob(), &claim).dry_run().await?;
+            let batch_ref_time = result.exec_result.gas_consumed.ref_time();
+            let result = client.call(&ink_e2e::bob(), &claim).submit().await?;
+            assert_eq!(result.return_value(), Ok(()));
+
+            // Then
+            assert!(
+                batch_ref_time < single_claims_ref_time,
+                "batch {batch_ref_time} should cost less than single claims {single_claims_ref_time}"
+            );
+            let claimed_parts = call_builder.claimed_parts();
+            let result = client.call(&ink_e2e::alice(), &claimed_parts).dry_run().await?;
+            assert_eq!(result.return_value(), vec![0, 1, 2, 3]);
+
             Ok(())
         }
 
         #[ink_e2e::test]
-        async fn test_partial_fill_scenario(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // This test would simulate a partial-fill scenario where only some parts are claimed
-            // Implementation would require setting up proper Merkle proofs
+        async fn test_token_escrow_claim(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use mock_psp22::{MockPsp22, MockPsp22Ref, PSP22};
+
+            // Given
+            let maker = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let taker = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let amount = 1_000;
+
+            let mut token_constructor = MockPsp22Ref::new(1_000_000);
+            let token = client
+                .instantiate("mock_psp22", &ink_e2e::alice(), &mut token_constructor)
+                .submit()
+                .await
+                .expect("token instantiate failed");
+            let mut token_calls = token.call_builder::<MockPsp22>();
+            let transfer = token_calls.transfer(taker, amount, Vec::new());
+            client.call(&ink_e2e::alice(), &transfer).submit().await?;
+
+            // Single part: the root is the leaf itself
+            let secret = Hash::from([7u8; 32]);
+            let mut merkle_root = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Keccak256>(secret.as_ref(), &mut merkle_root);
+
+            let mut constructor = EscrowDstRef::new(EscrowParams {
+                maker,
+                taker,
+                merkle_root: Hash::from(merkle_root),
+                parts_count: 1,
+                timelocks: Timelocks::new(0, u32::MAX, u32::MAX, u32::MAX),
+                time_unit: TimeUnit::Milliseconds,
+                token: Some(token.account_id),
+                asset_id: None,
+                amount,
+                safety_deposit: 0,
+                proof_mode: ProofMode::Positional,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            });
+            let contract = client
+                .instantiate("escrow_dst", &ink_e2e::bob(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut call_builder = contract.call_builder::<EscrowDst>();
+
+            // When
+            let approve = token_calls.approve(contract.account_id, amount);
+            client.call(&ink_e2e::bob(), &approve).submit().await?;
+            let fund = call_builder.fund_token(amount);
+            let result = client.call(&ink_e2e::bob(), &fund).submit().await?;
+            assert_eq!(result.return_value(), Ok(()));
+
+            let claim = call_builder.claim_part(vec![], secret, 0);
+            let result = client.call(&ink_e2e::bob(), &claim).submit().await?;
+            assert_eq!(result.return_value(), Ok(()));
+
+            // Then
+            let balance = token_calls.balance_of(maker);
+            let result = client.call(&ink_e2e::alice(), &balance).dry_run().await?;
+            assert_eq!(result.return_value(), amount);
+
+            let balance = token_calls.balance_of(contract.account_id);
+            let result = client.call(&ink_e2e::alice(), &balance).dry_run().await?;
+            assert_eq!(result.return_value(), 0);
+
             Ok(())
         }
 
         #[ink_e2e::test]
-        async fn test_refund_scenario(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // This test would simulate a refund scenario after expiry
+        async fn test_token_escrow_claim_and_refund(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use mock_psp22::{MockPsp22, MockPsp22Ref, PSP22};
+
+            // Given
+            let maker = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let taker = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let amount = 1_000;
+
+            let mut token_constructor = MockPsp22Ref::new(1_000_000);
+            let token = client
+                .instantiate("mock_psp22", &ink_e2e::alice(), &mut token_constructor)
+                .submit()
+                .await
+                .expect("token instantiate failed");
+            let mut token_calls = token.call_builder::<MockPsp22>();
+            let transfer = token_calls.transfer(taker, amount, Vec::new());
+            client.call(&ink_e2e::alice(), &transfer).submit().await?;
+
+            // Two positional parts: the root hashes both leaves
+            let secrets = [Hash::from([7u8; 32]), Hash::from([8u8; 32])];
+            let leaves = secrets.map(|secret| {
+                let mut leaf = [0u8; 32];
+                ink::env::hash_bytes::<ink::env::hash::Keccak256>(secret.as_ref(), &mut leaf);
+                leaf
+            });
+            let mut merkle_root = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Keccak256>(&leaves.concat(), &mut merkle_root);
+
+            // Withdrawal for 6 blocks after deployment, then the taker's cancellation
+            let mut constructor = EscrowDstRef::new(EscrowParams {
+                maker,
+                taker,
+                merkle_root: Hash::from(merkle_root),
+                parts_count: 2,
+                timelocks: Timelocks::new(0, 6, 6, 12),
+                time_unit: TimeUnit::Blocks,
+                token: Some(token.account_id),
+                asset_id: None,
+                amount,
+                safety_deposit: 0,
+                proof_mode: ProofMode::Positional,
+                hash_algorithm: HashAlgorithm::Keccak256,
+                leaf_domain: None,
+                order_hash: Hash::from([4u8; 32]),
+                fill_index: None,
+            });
+            let contract = client
+                .instantiate("escrow_dst", &ink_e2e::bob(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut call_builder = contract.call_builder::<EscrowDst>();
+
+            let approve = token_calls.approve(contract.account_id, amount);
+            client.call(&ink_e2e::bob(), &approve).submit().await?;
+            let fund = call_builder.fund_token(amount);
+            let result = client.call(&ink_e2e::bob(), &fund).submit().await?;
+            assert_eq!(result.return_value(), Ok(()));
+
+            // When the maker's first half is claimed
+            let claim = call_builder.claim_part(vec![Hash::from(leaves[1])], secrets[0], 0);
+            let result = client.call(&ink_e2e::bob(), &claim).submit().await?;
+            assert_eq!(result.return_value(), Ok(()));
+
+            // and the escrow expires before the second, each call sealing a block
+            let current_stage = call_builder.current_stage();
+            for _ in 0..12 {
+                let stage = client.call(&ink_e2e::alice(), &current_stage).dry_run().await?;
+                if stage.return_value() == Stage::ExclusiveCancellation {
+                    break;
+                }
+                let tick = token_calls.transfer(maker, 0, Vec::new());
+                client.call(&ink_e2e::alice(), &tick).submit().await?;
+            }
+            let refund = call_builder.refund();
+            let result = client.call(&ink_e2e::bob(), &refund).submit().await?;
+            assert_eq!(result.return_value(), Ok(()));
+
+            // Then each side holds its half and the escrow nothing
+            for (account, expected) in [(maker, amount / 2), (taker, amount / 2), (contract.account_id, 0)] {
+                let balance = token_calls.balance_of(account);
+                let result = client.call(&ink_e2e::alice(), &balance).dry_run().await?;
+                assert_eq!(result.return_value(), expected);
+            }
+
             Ok(())
         }
     }
@@ -1,69 +1,229 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
-#[ink::contract]
+pub use self::escrow_factory::{EscrowFactory, EscrowFactoryRef, MakerSignature};
+
+#[ink::contract(env = escrow_dst::EscrowEnvironment)]
+// `deploy_escrow` takes the order alongside the escrow parameters, which makes the
+// generated message enum lopsided; messages are decoded once per call
+#[allow(clippy::large_enum_variant)]
 mod escrow_factory {
+    use escrow_dst::{
+        fill_secret_index, EscrowDstRef, EscrowParams, LeafDomain, Order, OrderDomain, OrderEncoding,
+    };
     use ink::prelude::vec::Vec;
+    use ink::scale::Encode;
+    use ink::ToAccountId;
 
     /// Defines the storage of your contract.
     #[ink(storage)]
     pub struct EscrowFactory {
         /// The code hash of the EscrowDst contract
         escrow_dst_code_hash: Hash,
-        /// Mapping from salt to deployed escrow address
+        /// Identifier of this chain in orders, see `OrderDomain`
+        chain_id: u64,
+        /// Mapping from salt to deployed escrow address, salts are derived from the
+        /// order hash and fill index
         deployed_escrows: ink::storage::Mapping<Hash, AccountId>,
+        /// Taking amount locked by the escrows deployed so far, by order hash
+        filled_amounts: ink::storage::Mapping<Hash, Balance>,
+        /// Number of escrows deployed by this factory
+        escrow_count: u32,
+        /// Escrows by maker and position, in deployment order
+        maker_escrows: ink::storage::Mapping<(AccountId, u32), AccountId>,
+        /// Number of escrows per maker
+        maker_escrow_count: ink::storage::Mapping<AccountId, u32>,
+        /// Escrows by taker and position, in deployment order
+        taker_escrows: ink::storage::Mapping<(AccountId, u32), AccountId>,
+        /// Number of escrows per taker
+        taker_escrow_count: ink::storage::Mapping<AccountId, u32>,
+    }
+
+    /// Most escrows returned by one page of `escrows_by_maker`/`escrows_by_taker`
+    pub const MAX_PAGE_SIZE: u32 = 100;
+
+    /// Maker's signature over the order hash
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum MakerSignature {
+        /// sr25519 signature; the maker's account is the public key
+        ///
+        /// Only with the `sr25519` feature: `sr25519_verify` is part of pallet-contracts'
+        /// unstable interface, and chains without `UnsafeUnstableInterface` reject code
+        /// importing it.
+        #[cfg(feature = "sr25519")]
+        Sr25519([u8; 64]),
+        /// secp256k1 signature `r ++ s ++ v`, checked the way its `OrderEncoding` is
+        /// signed
+        ///
+        /// `Scale` orders carry a Substrate ECDSA signature over `blake2_256(order_hash)`,
+        /// the maker's account being `blake2_256` of the compressed public key. `Eip712`
+        /// orders carry an Ethereum signature over the order hash itself, as returned by
+        /// `eth_signTypedData`, the maker's account being the signer's address
+        /// left-padded with zeros.
+        Ecdsa([u8; 65]),
     }
 
+    /// Errors that can occur when deploying escrows
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        /// Escrow with this salt already exists
+        EscrowAlreadyExists,
+        /// EscrowDst instantiation failed
+        DeploymentFailed,
+        /// Escrow parameters don't match the order
+        OrderMismatch,
+        /// Fill index is not the secret index the cumulative fill ends at
+        InvalidFillIndex,
+        /// Fill amount is zero, overfills the order, or reaches no new secret
+        InvalidFillAmount,
+        /// Signature is not the maker's signature over the order hash
+        InvalidSignature,
+        /// Caller is not the order's taker
+        NotTaker,
+    }
+
+    /// Result type for factory operations
+    pub type Result<T> = core::result::Result<T, Error>;
+
     #[ink(event)]
     pub struct EscrowDeployed {
         #[ink(topic)]
         salt: Hash,
         #[ink(topic)]
         escrow: AccountId,
+        #[ink(topic)]
+        order_hash: Hash,
+        fill_index: Option<u32>,
         maker: AccountId,
         taker: AccountId,
     }
 
     impl EscrowFactory {
-        /// Constructor that initializes the factory with the EscrowDst code hash
+        /// Constructor that initializes the factory with the EscrowDst code hash and the
+        /// identifier orders use for this chain
         #[ink(constructor)]
-        pub fn new(escrow_dst_code_hash: Hash) -> Self {
+        pub fn new(escrow_dst_code_hash: Hash, chain_id: u64) -> Self {
             Self {
                 escrow_dst_code_hash,
+                chain_id,
                 deployed_escrows: ink::storage::Mapping::default(),
+                filled_amounts: ink::storage::Mapping::default(),
+                escrow_count: 0,
+                maker_escrows: ink::storage::Mapping::default(),
+                maker_escrow_count: ink::storage::Mapping::default(),
+                taker_escrows: ink::storage::Mapping::default(),
+                taker_escrow_count: ink::storage::Mapping::default(),
             }
         }
 
-        /// Deploy a new EscrowDst instance with the given salt
-        #[ink(message)]
+        /// Deploy a new EscrowDst instance for a maker-signed order
+        ///
+        /// `params` must be the order's destination leg on this chain and carry its hash
+        /// in `encoding` under this factory's `OrderDomain`, which the maker signs. The
+        /// salt is derived from that hash, so an order deploys at most one escrow, or one
+        /// per `params.fill_index` for partial fills. Partial fills lock part of the
+        /// taking amount each, and their `fill_index` must be the secret index their
+        /// cumulative fill ends at, see `fill_secret_index`; the escrow only releases that
+        /// secret's fill. Only the order's taker deploys its escrows, forwarding the
+        /// transferred value as their safety deposit and funding.
+        #[ink(message, payable)]
         pub fn deploy_escrow(
             &mut self,
-            salt: Hash,
-            maker: AccountId,
-            taker: AccountId,
-            _merkle_root: Hash,
-            _parts_count: u32,
-            _expiry_timestamp: u64,
-        ) {
-            // Check if escrow with this salt already exists
-            if self.deployed_escrows.get(salt).is_some() {
-                ink::env::debug_println!("Escrow already exists");
-                return;
-            }
-
-            // For now, we'll just store the parameters and emit an event
-            // In a real implementation, you would use the proper contract instantiation API
-            let escrow_address = AccountId::from([0u8; 32]); // Placeholder
-            
-            // Store the deployed escrow address
+            order: Order,
+            encoding: OrderEncoding,
+            params: EscrowParams,
+            signature: MakerSignature,
+        ) -> Result<AccountId> {
+            // Fills count towards the order whether or not they are funded, so only the
+            // taker may take them
+            if self.env().caller() != order.taker {
+                return Err(Error::NotTaker);
+            }
+
+            // Only deploy escrows for orders the maker signed
+            let order_hash = order.hash(&self.order_domain(), encoding);
+            let fill_index = params.fill_index;
+            self.check_params(&order, order_hash, &params)?;
+            let filled_amount = self.check_fill(&order, order_hash, params.amount, fill_index)?;
+
+            // Check if escrow for this order and fill already exists
+            let salt = Self::escrow_salt(order_hash, fill_index);
+            if self.deployed_escrows.contains(salt) {
+                return Err(Error::EscrowAlreadyExists);
+            }
+
+            Self::verify_signature(order_hash, encoding, order.maker, &signature)?;
+
+            // Instantiate EscrowDst deterministically from the code hash and salt
+            let (maker, taker) = (params.maker, params.taker);
+            let escrow = EscrowDstRef::new(params)
+                .code_hash(self.escrow_dst_code_hash)
+                .endowment(self.env().transferred_value())
+                .salt_bytes(salt)
+                .try_instantiate()
+                .map_err(|_| Error::DeploymentFailed)?
+                .map_err(|_| Error::DeploymentFailed)?
+                .map_err(|_| Error::DeploymentFailed)?;
+            let escrow_address = escrow.to_account_id();
+
+            // Store the deployed escrow address and index it for its maker and taker
             self.deployed_escrows.insert(salt, &escrow_address);
+            self.filled_amounts.insert(order_hash, &filled_amount);
+            self.index_escrow(maker, taker, escrow_address);
 
             // Emit event
             self.env().emit_event(EscrowDeployed {
                 salt,
                 escrow: escrow_address,
+                order_hash,
+                fill_index,
                 maker,
                 taker,
             });
+
+            Ok(escrow_address)
+        }
+
+        /// Predict the address `deploy_escrow` will assign for the given parameters
+        ///
+        /// Mirrors the pallet-contracts address derivation:
+        /// `blake2_256("contract_addr_v1" ++ deployer ++ code_hash ++ input_data ++ salt)`.
+        #[ink(message)]
+        pub fn predict_escrow(&self, params: EscrowParams) -> AccountId {
+            let salt = Self::escrow_salt(params.order_hash, params.fill_index);
+            let create_params = EscrowDstRef::new(params)
+                .code_hash(self.escrow_dst_code_hash)
+                .endowment(0)
+                .salt_bytes(salt)
+                .params();
+            let input_data = create_params.exec_input().encode();
+
+            self.contract_address(&input_data, salt.as_ref())
+        }
+
+        /// Get the hash identifying an order under this factory's domain, which its maker signs
+        #[ink(message)]
+        pub fn get_order_hash(&self, order: Order, encoding: OrderEncoding) -> Hash {
+            order.hash(&self.order_domain(), encoding)
+        }
+
+        /// Get the identifier orders use for this chain
+        #[ink(message)]
+        pub fn get_chain_id(&self) -> u64 {
+            self.chain_id
+        }
+
+        /// Get the salt an escrow for the given order hash and fill is deployed with
+        #[ink(message)]
+        pub fn get_escrow_salt(&self, order_hash: Hash, fill_index: Option<u32>) -> Hash {
+            Self::escrow_salt(order_hash, fill_index)
+        }
+
+        /// Get the taking amount locked by the escrows deployed for an order so far
+        #[ink(message)]
+        pub fn get_filled_amount(&self, order_hash: Hash) -> Balance {
+            self.filled_amounts.get(order_hash).unwrap_or(0)
         }
 
         /// Get the deployed escrow address for a given salt
@@ -72,53 +232,925 @@ mod escrow_factory {
             self.deployed_escrows.get(salt)
         }
 
+        /// Get the number of escrows deployed by this factory
+        #[ink(message)]
+        pub fn get_escrow_count(&self) -> u32 {
+            self.escrow_count
+        }
+
+        /// Get the number of escrows made by `maker`
+        #[ink(message)]
+        pub fn get_maker_escrow_count(&self, maker: AccountId) -> u32 {
+            self.maker_escrow_count.get(maker).unwrap_or(0)
+        }
+
+        /// Get the number of escrows taken by `taker`
+        #[ink(message)]
+        pub fn get_taker_escrow_count(&self, taker: AccountId) -> u32 {
+            self.taker_escrow_count.get(taker).unwrap_or(0)
+        }
+
+        /// Get up to `limit` escrows made by `maker`, oldest first, skipping `offset`
+        ///
+        /// Pages hold at most `MAX_PAGE_SIZE` escrows; an offset past the end gives an
+        /// empty page.
+        #[ink(message)]
+        pub fn escrows_by_maker(&self, maker: AccountId, offset: u32, limit: u32) -> Vec<AccountId> {
+            Self::page(self.get_maker_escrow_count(maker), offset, limit)
+                .filter_map(|position| self.maker_escrows.get((maker, position)))
+                .collect()
+        }
+
+        /// Get up to `limit` escrows taken by `taker`, oldest first, skipping `offset`
+        #[ink(message)]
+        pub fn escrows_by_taker(&self, taker: AccountId, offset: u32, limit: u32) -> Vec<AccountId> {
+            Self::page(self.get_taker_escrow_count(taker), offset, limit)
+                .filter_map(|position| self.taker_escrows.get((taker, position)))
+                .collect()
+        }
+
         /// Get the EscrowDst code hash
         #[ink(message)]
         pub fn get_escrow_dst_code_hash(&self) -> Hash {
             self.escrow_dst_code_hash
         }
+
+        /// Append a deployed escrow to the global count and its maker's and taker's indexes
+        fn index_escrow(&mut self, maker: AccountId, taker: AccountId, escrow: AccountId) {
+            self.escrow_count = self.escrow_count.saturating_add(1);
+
+            let maker_count = self.get_maker_escrow_count(maker);
+            self.maker_escrows.insert((maker, maker_count), &escrow);
+            self.maker_escrow_count.insert(maker, &maker_count.saturating_add(1));
+
+            let taker_count = self.get_taker_escrow_count(taker);
+            self.taker_escrows.insert((taker, taker_count), &escrow);
+            self.taker_escrow_count.insert(taker, &taker_count.saturating_add(1));
+        }
+
+        /// Index positions of a page of `limit` escrows from `offset`, out of `count`
+        fn page(count: u32, offset: u32, limit: u32) -> core::ops::Range<u32> {
+            let end = offset.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+            offset..end
+        }
+
+        /// Domain orders deployed by this factory are hashed under
+        fn order_domain(&self) -> OrderDomain {
+            OrderDomain {
+                chain_id: self.chain_id,
+                factory: self.env().account_id(),
+            }
+        }
+
+        /// Domain the leaves of an order's secrets tree are bound to, see `LeafDomain`
+        fn leaf_domain(&self, order: &Order) -> LeafDomain {
+            LeafDomain {
+                chain_id: self.chain_id,
+                factory: self.env().account_id(),
+                nonce: order.nonce,
+            }
+        }
+
+        /// Check that `params` deploy the destination leg on this chain of the order
+        /// hashing to `order_hash`
+        ///
+        /// Every parameter must be the order's. A whole-order escrow locks the full taking
+        /// amount; a partial fill at most that, at one of the order's N+1 secret indexes.
+        /// Either posts the share of the order's safety deposit its amount is of the
+        /// taking amount, rounded down.
+        fn check_params(&self, order: &Order, order_hash: Hash, params: &EscrowParams) -> Result<()> {
+            let amount_matches = match params.fill_index {
+                None => params.amount == order.taking_amount,
+                Some(_) => params.amount <= order.taking_amount,
+            };
+            let safety_deposit = order
+                .safety_deposit
+                .checked_mul(params.amount)
+                .and_then(|scaled| scaled.checked_div(order.taking_amount))
+                .ok_or(Error::OrderMismatch)?;
+            let expected = EscrowParams {
+                maker: order.maker,
+                taker: order.taker,
+                merkle_root: order.merkle_root,
+                parts_count: order.parts_count,
+                timelocks: order.timelocks,
+                time_unit: order.time_unit,
+                token: order.token,
+                asset_id: order.asset_id,
+                amount: params.amount,
+                safety_deposit,
+                proof_mode: order.proof_mode,
+                hash_algorithm: order.hash_algorithm,
+                leaf_domain: order.bind_leaves.then_some(self.leaf_domain(order)),
+                order_hash,
+                fill_index: params.fill_index,
+            };
+            if order.dst_chain_id != self.chain_id || !amount_matches || *params != expected {
+                return Err(Error::OrderMismatch);
+            }
+            if params.fill_index.is_some_and(|index| index > order.parts_count) {
+                return Err(Error::InvalidFillIndex);
+            }
+            Ok(())
+        }
+
+        /// Check that an escrow locking `amount` at `fill_index` fits the order's fills so
+        /// far, returning the order's filled amount once it is deployed
+        ///
+        /// A whole-order escrow needs an unfilled order; a partial fill must reveal the
+        /// secret its cumulative fill ends at, which rules out overfills.
+        fn check_fill(
+            &self,
+            order: &Order,
+            order_hash: Hash,
+            amount: Balance,
+            fill_index: Option<u32>,
+        ) -> Result<Balance> {
+            let filled = self.get_filled_amount(order_hash);
+            let Some(fill_index) = fill_index else {
+                if filled > 0 {
+                    return Err(Error::InvalidFillAmount);
+                }
+                return Ok(amount);
+            };
+
+            let index = fill_secret_index(order.taking_amount, order.parts_count, filled, amount)
+                .ok_or(Error::InvalidFillAmount)?;
+            if fill_index != index {
+                return Err(Error::InvalidFillIndex);
+            }
+            Ok(filled.saturating_add(amount))
+        }
+
+        /// Salt of the escrow for `order_hash`: the hash itself for a whole-order escrow,
+        /// `blake2_256(order_hash ++ fill_index)` with a SCALE-encoded index for a partial fill
+        fn escrow_salt(order_hash: Hash, fill_index: Option<u32>) -> Hash {
+            match fill_index {
+                None => order_hash,
+                Some(index) => {
+                    let mut output = [0u8; 32];
+                    ink::env::hash_encoded::<ink::env::hash::Blake2x256, _>(
+                        &(order_hash, index),
+                        &mut output,
+                    );
+                    Hash::from(output)
+                }
+            }
+        }
+
+        /// Check that `signature` is `maker`'s signature over `order_hash` in `encoding`
+        fn verify_signature(
+            order_hash: Hash,
+            encoding: OrderEncoding,
+            maker: AccountId,
+            signature: &MakerSignature,
+        ) -> Result<()> {
+            match signature {
+                #[cfg(feature = "sr25519")]
+                MakerSignature::Sr25519(signature) => {
+                    ink::env::sr25519_verify(signature, order_hash.as_ref(), maker.as_ref())
+                        .map_err(|_| Error::InvalidSignature)
+                }
+                MakerSignature::Ecdsa(signature) => {
+                    use ink::env::hash::Blake2x256;
+                    let mut message_hash: [u8; 32] = order_hash.into();
+                    // Substrate ECDSA signs the blake2 hash of the message, Ethereum
+                    // wallets the EIP-712 digest as is
+                    if encoding == OrderEncoding::Scale {
+                        ink::env::hash_bytes::<Blake2x256>(order_hash.as_ref(), &mut message_hash);
+                    }
+                    let mut public_key = [0u8; 33];
+                    ink::env::ecdsa_recover(signature, &message_hash, &mut public_key)
+                        .map_err(|_| Error::InvalidSignature)?;
+
+                    let mut signer = [0u8; 32];
+                    match encoding {
+                        OrderEncoding::Scale => {
+                            ink::env::hash_bytes::<Blake2x256>(&public_key, &mut signer)
+                        }
+                        OrderEncoding::Eip712 => {
+                            let mut address = [0u8; 20];
+                            ink::env::ecdsa_to_eth_address(&public_key, &mut address)
+                                .map_err(|_| Error::InvalidSignature)?;
+                            signer[12..].copy_from_slice(&address);
+                        }
+                    }
+                    if AccountId::from(signer) != maker {
+                        return Err(Error::InvalidSignature);
+                    }
+                    Ok(())
+                }
+            }
+        }
+
+        /// Derive a contract address the way pallet-contracts does for this deployer
+        fn contract_address(&self, input_data: &[u8], salt: &[u8]) -> AccountId {
+            use ink::env::hash::Blake2x256;
+            let entropy = (
+                b"contract_addr_v1",
+                self.env().account_id(),
+                self.escrow_dst_code_hash,
+                input_data,
+                salt,
+            )
+                .encode();
+
+            let mut output = [0u8; 32];
+            ink::env::hash_bytes::<Blake2x256>(&entropy, &mut output);
+            AccountId::from(output)
+        }
     }
 
     /// Unit tests
     #[cfg(test)]
     mod tests {
         use super::*;
+        use escrow_dst::{EscrowDst, HashAlgorithm, LeafDomain, ProofMode, TimeUnit, Timelocks};
 
         #[ink::test]
         fn test_new() {
             let code_hash = Hash::from([1u8; 32]);
-            let factory = EscrowFactory::new(code_hash);
+            let factory = EscrowFactory::new(code_hash, 1000);
             assert_eq!(factory.get_escrow_dst_code_hash(), code_hash);
+            assert_eq!(factory.get_chain_id(), 1000);
         }
 
         #[ink::test]
         fn test_get_deployed_escrow() {
             let code_hash = Hash::from([1u8; 32]);
-            let factory = EscrowFactory::new(code_hash);
+            let factory = EscrowFactory::new(code_hash, 1000);
             let salt = Hash::from([2u8; 32]);
             assert_eq!(factory.get_deployed_escrow(salt), None);
         }
 
+        fn test_params() -> EscrowParams {
+            EscrowParams {
+                maker: AccountId::from([3u8; 32]),
+                taker: AccountId::from([4u8; 32]),
+                merkle_root: Hash::from([5u8; 32]),
+                parts_count: 4,
+                timelocks: Timelocks::new(60, 900, 1000, 2000),
+                time_unit: TimeUnit::Seconds,
+                token: Some(AccountId::from([6u8; 32])),
+                asset_id: None,
+                amount: 400,
+                safety_deposit: 25,
+                proof_mode: ProofMode::SortedPairs,
+                hash_algorithm: HashAlgorithm::Sha256,
+                leaf_domain: Some(LeafDomain {
+                    chain_id: 1000,
+                    factory: ink::env::account_id::<ink::env::DefaultEnvironment>(),
+                    nonce: 0,
+                }),
+                order_hash: Hash::from([7u8; 32]),
+                fill_index: None,
+            }
+        }
+
+        /// Order made by `maker` whose destination leg on chain 1000 is `test_params`
+        fn test_order(maker: AccountId) -> Order {
+            let params = test_params();
+            Order {
+                maker,
+                taker: params.taker,
+                merkle_root: params.merkle_root,
+                parts_count: params.parts_count,
+                src_chain_id: 1,
+                dst_chain_id: 1000,
+                making_amount: 1_000,
+                taking_amount: params.amount,
+                token: params.token,
+                asset_id: params.asset_id,
+                timelocks: params.timelocks,
+                time_unit: params.time_unit,
+                safety_deposit: params.safety_deposit,
+                proof_mode: params.proof_mode,
+                hash_algorithm: params.hash_algorithm,
+                bind_leaves: true,
+                nonce: 0,
+            }
+        }
+
+        /// Escrow parameters `factory` deploys the destination leg of `order` with
+        fn order_params(factory: &EscrowFactory, order: &Order, encoding: OrderEncoding) -> EscrowParams {
+            let params = test_params();
+            EscrowParams {
+                maker: order.maker,
+                leaf_domain: params.leaf_domain.map(|domain| LeafDomain { nonce: order.nonce, ..domain }),
+                order_hash: factory.get_order_hash(order.clone(), encoding),
+                ..params
+            }
+        }
+
+        /// Sign an order hash with a dev sr25519 key
+        #[cfg(feature = "sr25519")]
+        fn sign_sr25519(keypair: &ink_e2e::Keypair, order_hash: Hash) -> MakerSignature {
+            MakerSignature::Sr25519(keypair.sign(order_hash.as_ref()).0)
+        }
+
+        /// Sign an order hash with a dev ECDSA key
+        fn sign_ecdsa(keypair: &ink_e2e::subxt_signer::ecdsa::Keypair, order_hash: Hash) -> MakerSignature {
+            MakerSignature::Ecdsa(keypair.sign(order_hash.as_ref()).0)
+        }
+
+        /// Account of a Substrate ECDSA key, `blake2_256` of the compressed public key
+        fn ecdsa_account(keypair: &ink_e2e::subxt_signer::ecdsa::Keypair) -> AccountId {
+            let mut account = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(&keypair.public_key().0, &mut account);
+            AccountId::from(account)
+        }
+
         #[ink::test]
-        fn test_deploy_escrow() {
+        fn test_deploy_escrow_already_exists() {
             let code_hash = Hash::from([1u8; 32]);
-            let mut factory = EscrowFactory::new(code_hash);
-            let salt = Hash::from([2u8; 32]);
+            let mut factory = EscrowFactory::new(code_hash, 1000);
+            let order = test_order(AccountId::from([3u8; 32]));
+            let params = order_params(&factory, &order, OrderEncoding::Scale);
+            let salt = factory.get_escrow_salt(params.order_hash, None);
+
+            // Instantiation is not supported off-chain, so seed the salt directly
+            factory.deployed_escrows.insert(salt, &AccountId::from([6u8; 32]));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(order.taker);
+            assert_eq!(
+                factory.deploy_escrow(order, OrderEncoding::Scale, params, MakerSignature::Ecdsa([0u8; 65])),
+                Err(Error::EscrowAlreadyExists)
+            );
+            assert_eq!(factory.get_deployed_escrow(salt), Some(AccountId::from([6u8; 32])));
+        }
+
+        #[ink::test]
+        fn test_escrow_salt_from_order_hash() {
+            let factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let order_hash = Hash::from([7u8; 32]);
+
+            // A whole-order escrow is deployed under the order hash itself
+            assert_eq!(factory.get_escrow_salt(order_hash, None), order_hash);
+
+            // Partial fills: blake2_256(order_hash ++ u32 little-endian fill index)
+            let mut preimage = order_hash.as_ref().to_vec();
+            preimage.extend_from_slice(&2u32.to_le_bytes());
+            let mut expected = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(&preimage, &mut expected);
+            assert_eq!(factory.get_escrow_salt(order_hash, Some(2)), Hash::from(expected));
+
+            // Every order and fill gets its own salt
+            let salts = [
+                factory.get_escrow_salt(order_hash, None),
+                factory.get_escrow_salt(order_hash, Some(0)),
+                factory.get_escrow_salt(order_hash, Some(1)),
+                factory.get_escrow_salt(Hash::from([8u8; 32]), Some(0)),
+            ];
+            for (i, salt) in salts.iter().enumerate() {
+                assert!(salts[i + 1..].iter().all(|other| other != salt));
+            }
+        }
+
+        #[ink::test]
+        fn test_deploy_escrow_partial_fills() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let order = test_order(AccountId::from([3u8; 32]));
+            let params = order_params(&factory, &order, OrderEncoding::Scale);
+            let order_hash = params.order_hash;
+            let partial = |fill_index: u32| EscrowParams {
+                amount: 100,
+                safety_deposit: 6,
+                fill_index: Some(fill_index),
+                ..params.clone()
+            };
+
+            // A partial fill may lock part of the taking amount, at secret indexes 0..=N,
+            // with its share of the safety deposit rounded down
+            for fill_index in [0, 2, order.parts_count] {
+                assert_eq!(factory.check_params(&order, order_hash, &partial(fill_index)), Ok(()));
+            }
+            assert_eq!(
+                factory.check_params(&order, order_hash, &EscrowParams { fill_index: None, ..partial(0) }),
+                Err(Error::OrderMismatch)
+            );
+            for safety_deposit in [5, 7, params.safety_deposit] {
+                assert_eq!(
+                    factory.check_params(&order, order_hash, &EscrowParams { safety_deposit, ..partial(0) }),
+                    Err(Error::OrderMismatch)
+                );
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(order.taker);
+            let deploy = |factory: &mut EscrowFactory, params: EscrowParams| {
+                factory.deploy_escrow(
+                    order.clone(),
+                    OrderEncoding::Scale,
+                    params,
+                    MakerSignature::Ecdsa([0u8; 65]),
+                )
+            };
+
+            // But never more than the order, nor past the last secret, nor at another
+            // secret than the fill ends at
+            let overfill = EscrowParams { amount: 401, fill_index: Some(1), ..params.clone() };
+            assert_eq!(deploy(&mut factory, overfill), Err(Error::OrderMismatch));
+            assert_eq!(deploy(&mut factory, partial(5)), Err(Error::InvalidFillIndex));
+            assert_eq!(deploy(&mut factory, partial(1)), Err(Error::InvalidFillIndex));
+
+            // Each fill index deploys at most once
+            let salt = EscrowFactory::escrow_salt(order_hash, Some(0));
+            factory.deployed_escrows.insert(salt, &AccountId::from([6u8; 32]));
+            assert_eq!(deploy(&mut factory, partial(0)), Err(Error::EscrowAlreadyExists));
+
+            // Once 100 are filled, the next 100 end at secret 1
+            factory.filled_amounts.insert(order_hash, &100);
+            assert_eq!(deploy(&mut factory, partial(0)), Err(Error::InvalidFillIndex));
+            assert_eq!(deploy(&mut factory, partial(1)), Err(Error::InvalidSignature));
+        }
+
+        #[ink::test]
+        fn test_deploy_escrow_only_by_taker() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let alice = ink_e2e::subxt_signer::ecdsa::dev::alice();
+            let order = test_order(ecdsa_account(&alice));
+            let params = order_params(&factory, &order, OrderEncoding::Scale);
+            let partial = EscrowParams { amount: 100, safety_deposit: 6, fill_index: Some(0), ..params };
+            let signature = sign_ecdsa(&alice, partial.order_hash);
+
+            // Anyone holding the signed order could otherwise take its fills unfunded
+            for caller in [order.maker, AccountId::from([9u8; 32])] {
+                ink::env::test::set_caller::<ink::env::DefaultEnvironment>(caller);
+                assert_eq!(
+                    factory.deploy_escrow(order.clone(), OrderEncoding::Scale, partial.clone(), signature.clone()),
+                    Err(Error::NotTaker)
+                );
+            }
+            assert_eq!(factory.get_filled_amount(partial.order_hash), 0);
+
+            // The taker gets past the checks to instantiation, which the off-chain
+            // environment doesn't support
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(order.taker);
+            assert_eq!(factory.check_params(&order, partial.order_hash, &partial), Ok(()));
+            assert_eq!(
+                EscrowFactory::verify_signature(partial.order_hash, OrderEncoding::Scale, order.maker, &signature),
+                Ok(())
+            );
+        }
+
+        #[ink::test]
+        fn test_check_fill_tracks_order_fills() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let order = test_order(AccountId::from([3u8; 32]));
+            let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Scale);
+            let mut fill = |amount: Balance, fill_index: Option<u32>| -> Result<Balance> {
+                let filled = factory.check_fill(&order, order_hash, amount, fill_index)?;
+                factory.filled_amounts.insert(order_hash, &filled);
+                Ok(filled)
+            };
+
+            // 400 over 4 parts: each fill reveals the secret its cumulative fill ends at
+            assert_eq!(fill(100, Some(1)), Err(Error::InvalidFillIndex));
+            assert_eq!(fill(100, Some(0)), Ok(100));
+            assert_eq!(fill(150, Some(2)), Ok(250));
+
+            // Fills within the last bucket, of nothing, or past the order are rejected
+            assert_eq!(fill(10, Some(2)), Err(Error::InvalidFillAmount));
+            assert_eq!(fill(0, Some(2)), Err(Error::InvalidFillAmount));
+            assert_eq!(fill(151, Some(4)), Err(Error::InvalidFillAmount));
+
+            // As is the whole order once part of it is filled
+            assert_eq!(fill(400, None), Err(Error::InvalidFillAmount));
+
+            // The fill completing the order reveals the extra secret, and nothing is left
+            assert_eq!(fill(150, Some(3)), Err(Error::InvalidFillIndex));
+            assert_eq!(fill(150, Some(4)), Ok(400));
+            assert_eq!(fill(1, Some(4)), Err(Error::InvalidFillAmount));
+            assert_eq!(factory.get_filled_amount(order_hash), 400);
+        }
+
+        #[ink::test]
+        fn test_check_fill_whole_order() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let order = test_order(AccountId::from([3u8; 32]));
+            let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Scale);
+            assert_eq!(factory.get_filled_amount(order_hash), 0);
+
+            // An unfilled order can be taken whole, after which no fill fits
+            assert_eq!(factory.check_fill(&order, order_hash, 400, None), Ok(400));
+            factory.filled_amounts.insert(order_hash, &400);
+            assert_eq!(factory.check_fill(&order, order_hash, 100, Some(0)), Err(Error::InvalidFillAmount));
+            assert_eq!(factory.check_fill(&order, order_hash, 400, None), Err(Error::InvalidFillAmount));
+        }
+
+        #[ink::test]
+        fn test_maker_signatures() {
+            let factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+
+            // sr25519: the account is the public key
+            #[cfg(feature = "sr25519")]
+            {
+                let alice = ink_e2e::alice();
+                let order = test_order(ink_e2e::account_id(ink_e2e::AccountKeyring::Alice));
+                let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Scale);
+                let signature = sign_sr25519(&alice, order_hash);
+                assert_eq!(
+                    EscrowFactory::verify_signature(order_hash, OrderEncoding::Scale, order.maker, &signature),
+                    Ok(())
+                );
+            }
+
+            // Substrate ECDSA: the account is derived from the recovered public key
+            let bob = ink_e2e::subxt_signer::ecdsa::dev::bob();
+            let order = test_order(ecdsa_account(&bob));
+            let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Scale);
+            let signature = sign_ecdsa(&bob, order_hash);
+            assert_eq!(
+                EscrowFactory::verify_signature(order_hash, OrderEncoding::Scale, order.maker, &signature),
+                Ok(())
+            );
+
+            // It signs the blake2 hash, which an EIP-712 order is not checked against
+            let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Eip712);
+            let signature = sign_ecdsa(&bob, order_hash);
+            assert_eq!(
+                EscrowFactory::verify_signature(order_hash, OrderEncoding::Eip712, order.maker, &signature),
+                Err(Error::InvalidSignature)
+            );
+        }
+
+        /// `orderHash` of `test-vectors/order-hash.json`
+        const VECTOR_ORDER_HASH: &str = "0x34bbe19624f828b84dc7a064f140b6656ba2ce7c7b37d8fefaf36ce1afa984cc";
+
+        /// Hardhat account 0, `ETHERS_SIGNATURE`'s signer
+        const ETHERS_SIGNER: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+        /// ethers `signTypedData` of `test-vectors/order-hash.json` by `ETHERS_SIGNER`,
+        /// checked against ethers by `Ethereum/scripts/check-order-vectors.js`
+        const ETHERS_SIGNATURE: &str = "0x8df095414f4a8291a54ebe56678faf2e08e196336a3d5030ea887dcefc7b46b5\
+            1faf4cc5c5e0efc7d5bc997c62dd3e92644a1ef3b06923f3bf8ebb0b2c67b1e51c";
+
+        fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+            let hex = hex.trim_start_matches("0x");
+            let mut bytes = [0u8; N];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("invalid hex");
+            }
+            bytes
+        }
+
+        #[ink::test]
+        fn test_eip712_signature_from_ethers() {
+            let order_hash = Hash::from(from_hex::<32>(VECTOR_ORDER_HASH));
+            let signature = from_hex::<65>(ETHERS_SIGNATURE);
+            let address = from_hex::<20>(ETHERS_SIGNER);
+            let mut maker = [0u8; 32];
+            maker[12..].copy_from_slice(&address);
+            let maker = AccountId::from(maker);
+            let verify = |order_hash: Hash, encoding: OrderEncoding, maker: AccountId, signature: [u8; 65]| {
+                EscrowFactory::verify_signature(order_hash, encoding, maker, &MakerSignature::Ecdsa(signature))
+            };
+
+            // Recovered over the raw digest to the maker's left-padded address
+            assert_eq!(verify(order_hash, OrderEncoding::Eip712, maker, signature), Ok(()));
+
+            // with either form of the recovery id
+            let mut recovery_id = signature;
+            recovery_id[64] -= 27;
+            assert_eq!(verify(order_hash, OrderEncoding::Eip712, maker, recovery_id), Ok(()));
+
+            // But not as a Substrate signature, for another digest, or another account
+            assert_eq!(
+                verify(order_hash, OrderEncoding::Scale, maker, signature),
+                Err(Error::InvalidSignature)
+            );
+            assert_eq!(
+                verify(Hash::from([9u8; 32]), OrderEncoding::Eip712, maker, signature),
+                Err(Error::InvalidSignature)
+            );
+            let mut unpadded = [0u8; 32];
+            unpadded[..20].copy_from_slice(&address);
+            assert_eq!(
+                verify(order_hash, OrderEncoding::Eip712, AccountId::from(unpadded), signature),
+                Err(Error::InvalidSignature)
+            );
+        }
+
+        #[ink::test]
+        fn test_deploy_escrow_rejects_fabricated_orders() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let alice = ink_e2e::subxt_signer::ecdsa::dev::alice();
+            let order = test_order(ecdsa_account(&alice));
+            let params = order_params(&factory, &order, OrderEncoding::Scale);
+            let order_hash = params.order_hash;
+            let tampered = Order { nonce: 1, ..order.clone() };
+            let tampered_params = order_params(&factory, &tampered, OrderEncoding::Scale);
+            let eip712_params = order_params(&factory, &order, OrderEncoding::Eip712);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(order.taker);
+            let mut deploy = |order: &Order, params: &EscrowParams, signature: MakerSignature| {
+                factory.deploy_escrow(order.clone(), OrderEncoding::Scale, params.clone(), signature)
+            };
+
+            // Signed by someone other than the maker
+            let signature = sign_ecdsa(&ink_e2e::subxt_signer::ecdsa::dev::bob(), order_hash);
+            assert_eq!(deploy(&order, &params, signature), Err(Error::InvalidSignature));
+
+            // Maker's signature over a different order
+            let signature = sign_ecdsa(&alice, order_hash);
+            assert_eq!(
+                deploy(&tampered, &tampered_params, signature.clone()),
+                Err(Error::InvalidSignature)
+            );
+
+            // sr25519 signature by another key
+            #[cfg(feature = "sr25519")]
+            assert_eq!(
+                deploy(&order, &params, sign_sr25519(&ink_e2e::bob(), order_hash)),
+                Err(Error::InvalidSignature)
+            );
+
+            // Valid signature, but escrow parameters that differ from the order
+            let mismatched = [
+                EscrowParams { order_hash: Hash::from([9u8; 32]), ..params.clone() },
+                EscrowParams { taker: AccountId::from([9u8; 32]), ..params.clone() },
+                EscrowParams { merkle_root: Hash::from([9u8; 32]), ..params.clone() },
+                EscrowParams { parts_count: 5, ..params.clone() },
+                EscrowParams { amount: 401, ..params.clone() },
+                eip712_params,
+            ];
+            for params in mismatched {
+                assert_eq!(deploy(&order, &params, signature.clone()), Err(Error::OrderMismatch));
+            }
+            assert_eq!(factory.get_deployed_escrow(order_hash), None);
+        }
+
+        #[ink::test]
+        fn test_check_params_compares_every_field() {
+            let factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let order = test_order(AccountId::from([3u8; 32]));
+            let params = order_params(&factory, &order, OrderEncoding::Scale);
+            let order_hash = params.order_hash;
+            let domain = params.leaf_domain.expect("leaves are bound");
+            assert_eq!(factory.check_params(&order, order_hash, &params), Ok(()));
+
+            // Each parameter differing from the order on its own is rejected
+            let mismatched = [
+                EscrowParams { maker: AccountId::from([9u8; 32]), ..params.clone() },
+                EscrowParams { taker: AccountId::from([9u8; 32]), ..params.clone() },
+                EscrowParams { merkle_root: Hash::from([9u8; 32]), ..params.clone() },
+                EscrowParams { parts_count: 5, ..params.clone() },
+                EscrowParams { timelocks: Timelocks::new(60, 900, 1000, 2001), ..params.clone() },
+                EscrowParams { time_unit: TimeUnit::Milliseconds, ..params.clone() },
+                EscrowParams { token: None, ..params.clone() },
+                EscrowParams { token: Some(AccountId::from([9u8; 32])), ..params.clone() },
+                EscrowParams { token: None, asset_id: Some(1), ..params.clone() },
+                EscrowParams { amount: 399, ..params.clone() },
+                EscrowParams { safety_deposit: 24, ..params.clone() },
+                EscrowParams { proof_mode: ProofMode::Positional, ..params.clone() },
+                EscrowParams { hash_algorithm: HashAlgorithm::Keccak256, ..params.clone() },
+                EscrowParams { leaf_domain: None, ..params.clone() },
+                EscrowParams { leaf_domain: Some(LeafDomain { chain_id: 1, ..domain }), ..params.clone() },
+                EscrowParams {
+                    leaf_domain: Some(LeafDomain { factory: AccountId::from([9u8; 32]), ..domain }),
+                    ..params.clone()
+                },
+                EscrowParams { leaf_domain: Some(LeafDomain { nonce: 1, ..domain }), ..params.clone() },
+                EscrowParams { order_hash: Hash::from([9u8; 32]), ..params.clone() },
+            ];
+            for params in &mismatched {
+                assert_eq!(
+                    factory.check_params(&order, order_hash, params),
+                    Err(Error::OrderMismatch),
+                    "{params:?}"
+                );
+            }
+
+            // Orders for another destination chain, or without leaf binding, are checked too
+            let other_chain = Order { dst_chain_id: 1, ..order.clone() };
+            let params = order_params(&factory, &other_chain, OrderEncoding::Scale);
+            assert_eq!(
+                factory.check_params(&other_chain, params.order_hash, &params),
+                Err(Error::OrderMismatch)
+            );
+            let unbound = Order { bind_leaves: false, ..order.clone() };
+            let params = order_params(&factory, &unbound, OrderEncoding::Scale);
+            assert_eq!(
+                factory.check_params(&unbound, params.order_hash, &params),
+                Err(Error::OrderMismatch)
+            );
+            let params = EscrowParams { leaf_domain: None, ..params };
+            assert_eq!(factory.check_params(&unbound, params.order_hash, &params), Ok(()));
+        }
+
+        #[ink::test]
+        fn test_leaf_domain_tree_for_predicted_escrow() {
+            let factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
             let maker = AccountId::from([3u8; 32]);
-            let taker = AccountId::from([4u8; 32]);
-            let merkle_root = Hash::from([5u8; 32]);
-            let parts_count = 4;
-            let expiry_timestamp = 1000;
+            let secrets = [Hash::from([0x11; 32]), Hash::from([0x22; 32])];
 
-            factory.deploy_escrow(salt, maker, taker, merkle_root, parts_count, expiry_timestamp);
-            
-            // Should have deployed an escrow
-            assert!(factory.get_deployed_escrow(salt).is_some());
+            // The maker builds the tree first, from what is known before the order: this
+            // chain, the factory, the maker and the order's nonce
+            let leaf_domain = LeafDomain {
+                chain_id: factory.get_chain_id(),
+                factory: ink::env::account_id::<ink::env::DefaultEnvironment>(),
+                nonce: 3,
+            };
+            let template = EscrowParams {
+                maker,
+                parts_count: 1,
+                token: None,
+                leaf_domain: Some(leaf_domain),
+                ..test_params()
+            };
+            let leaves = [template.leaf(secrets[0], 0), template.leaf(secrets[1], 1)];
+            let (low, high) = if leaves[0].as_ref() <= leaves[1].as_ref() {
+                (leaves[0], leaves[1])
+            } else {
+                (leaves[1], leaves[0])
+            };
+            let merkle_root = template.hash_algorithm.hash(&[low.as_ref(), high.as_ref()].concat());
+
+            // Then signs an order over the root, whose hash and escrow address follow from it
+            let order = Order {
+                merkle_root,
+                parts_count: 1,
+                token: None,
+                nonce: 3,
+                ..test_order(maker)
+            };
+            let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Eip712);
+            let params = EscrowParams { merkle_root, order_hash, ..template };
+            assert_eq!(factory.check_params(&order, order_hash, &params), Ok(()));
+            let escrow = factory.predict_escrow(params.clone());
+
+            // The escrow at the predicted address accepts the tree's proofs
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(escrow);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(escrow, 1_000_000_425);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(425);
+            let mut dst = EscrowDst::new(params.clone()).expect("escrow creation failed");
+            assert_eq!(dst.get_domain_separator(), params.domain_separator());
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(params.taker);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(60_000);
+            assert_eq!(dst.claim_part(vec![leaves[1]], secrets[0], 0), Ok(()));
+            assert_eq!(dst.get_filled_amount(), 400);
+        }
+
+        #[ink::test]
+        fn test_order_hash_is_bound_to_factory() {
+            let order = test_order(AccountId::from([3u8; 32]));
+            let factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let order_hash = factory.get_order_hash(order.clone(), OrderEncoding::Eip712);
+
+            // The EIP-712 domain is this chain and factory
+            let domain = OrderDomain {
+                chain_id: 1000,
+                factory: ink::env::account_id::<ink::env::DefaultEnvironment>(),
+            };
+            assert_eq!(order_hash, order.hash(&domain, OrderEncoding::Eip712));
+
+            // Another chain id or factory address gives another hash
+            let other_chain = EscrowFactory::new(Hash::from([1u8; 32]), 1001);
+            assert_ne!(other_chain.get_order_hash(order.clone(), OrderEncoding::Eip712), order_hash);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(AccountId::from([0xfa; 32]));
+            assert_ne!(factory.get_order_hash(order, OrderEncoding::Eip712), order_hash);
+        }
+
+        #[ink::test]
+        fn test_escrow_indexes() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let (alice, bob, charlie) = (
+                AccountId::from([3u8; 32]),
+                AccountId::from([4u8; 32]),
+                AccountId::from([5u8; 32]),
+            );
+            assert_eq!(factory.get_escrow_count(), 0);
+            assert_eq!(factory.escrows_by_maker(alice, 0, 10), vec![]);
+
+            // Instantiation is not supported off-chain, so index escrows directly
+            let escrows: Vec<AccountId> = (0..5u8).map(|i| AccountId::from([100 + i; 32])).collect();
+            for (i, escrow) in escrows.iter().enumerate() {
+                let taker = if i % 2 == 0 { bob } else { charlie };
+                factory.index_escrow(alice, taker, *escrow);
+            }
+
+            assert_eq!(factory.get_escrow_count(), 5);
+            assert_eq!(factory.get_maker_escrow_count(alice), 5);
+            assert_eq!(factory.get_taker_escrow_count(bob), 3);
+            assert_eq!(factory.get_taker_escrow_count(charlie), 2);
+            assert_eq!(factory.get_maker_escrow_count(bob), 0);
+
+            // Makers and takers are indexed separately, in deployment order
+            assert_eq!(factory.escrows_by_maker(alice, 0, 10), escrows);
+            assert_eq!(factory.escrows_by_taker(bob, 0, 10), vec![escrows[0], escrows[2], escrows[4]]);
+            assert_eq!(factory.escrows_by_taker(charlie, 0, 10), vec![escrows[1], escrows[3]]);
+            assert_eq!(factory.escrows_by_maker(bob, 0, 10), vec![]);
+        }
+
+        #[ink::test]
+        fn test_escrow_pagination_boundaries() {
+            let mut factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let (maker, taker) = (AccountId::from([3u8; 32]), AccountId::from([4u8; 32]));
+            let escrows: Vec<AccountId> = (0..MAX_PAGE_SIZE + 5)
+                .map(|i| {
+                    let mut account = [0u8; 32];
+                    account[..4].copy_from_slice(&i.to_le_bytes());
+                    AccountId::from(account)
+                })
+                .collect();
+            for escrow in &escrows {
+                factory.index_escrow(maker, taker, *escrow);
+            }
+            let count = escrows.len() as u32;
+
+            // Consecutive pages cover every escrow exactly once
+            let mut paged = Vec::new();
+            for offset in (0..count).step_by(7) {
+                paged.extend(factory.escrows_by_maker(maker, offset, 7));
+            }
+            assert_eq!(paged, escrows);
+
+            // Zero limit, last element, exact end and offsets past the end
+            assert_eq!(factory.escrows_by_maker(maker, 0, 0), vec![]);
+            assert_eq!(factory.escrows_by_maker(maker, count - 1, 10), vec![escrows[count as usize - 1]]);
+            assert_eq!(factory.escrows_by_taker(taker, count - 3, 3), escrows[count as usize - 3..]);
+            assert_eq!(factory.escrows_by_taker(taker, count, 10), vec![]);
+            assert_eq!(factory.escrows_by_taker(taker, u32::MAX, u32::MAX), vec![]);
+
+            // Limits are capped at MAX_PAGE_SIZE
+            assert_eq!(
+                factory.escrows_by_maker(maker, 0, u32::MAX),
+                escrows[..MAX_PAGE_SIZE as usize]
+            );
+            assert_eq!(factory.escrows_by_taker(taker, 3, MAX_PAGE_SIZE + 1).len(), MAX_PAGE_SIZE as usize);
+        }
+
+        #[ink::test]
+        fn test_predict_escrow_matches_pallet_derivation() {
+            let code_hash = Hash::from([1u8; 32]);
+            let factory = EscrowFactory::new(code_hash, 1000);
+            let params = test_params();
+            let salt = params.order_hash;
+
+            // Constructor input: selector of `new` followed by the SCALE-encoded arguments
+            let mut input_data = ink::selector_bytes!("new").to_vec();
+            input_data.extend_from_slice(params.maker.as_ref());
+            input_data.extend_from_slice(params.taker.as_ref());
+            input_data.extend_from_slice(params.merkle_root.as_ref());
+            input_data.extend_from_slice(&params.parts_count.to_le_bytes());
+            for offset in [60u32, 900, 1000, 2000] {
+                input_data.extend_from_slice(&offset.to_le_bytes());
+            }
+            input_data.push(params.time_unit as u8);
+            input_data.push(1);
+            input_data.extend_from_slice(&[6u8; 32]);
+            input_data.push(0);
+            input_data.extend_from_slice(&params.amount.to_le_bytes());
+            input_data.extend_from_slice(&params.safety_deposit.to_le_bytes());
+            input_data.push(params.proof_mode as u8);
+            input_data.push(params.hash_algorithm as u8);
+            input_data.push(1);
+            input_data.extend_from_slice(&1000u64.to_le_bytes());
+            input_data.extend_from_slice(ink::env::account_id::<ink::env::DefaultEnvironment>().as_ref());
+            input_data.extend_from_slice(&0u64.to_le_bytes());
+            input_data.extend_from_slice(params.order_hash.as_ref());
+            input_data.push(0);
+
+            // Compact length prefixes: 271 bytes of input, 32 bytes of salt
+            let mut entropy = b"contract_addr_v1".to_vec();
+            entropy.extend_from_slice(ink::env::account_id::<ink::env::DefaultEnvironment>().as_ref());
+            entropy.extend_from_slice(code_hash.as_ref());
+            entropy.extend_from_slice(&[0x3d, 0x04]);
+            entropy.extend_from_slice(&input_data);
+            entropy.push(0x80);
+            entropy.extend_from_slice(salt.as_ref());
+
+            let mut expected = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(&entropy, &mut expected);
+
+            assert_eq!(factory.predict_escrow(params), AccountId::from(expected));
+        }
+
+        #[ink::test]
+        fn test_predict_escrow_is_deterministic() {
+            let factory = EscrowFactory::new(Hash::from([1u8; 32]), 1000);
+            let predicted = factory.predict_escrow(test_params());
+
+            // Same inputs always give the same address
+            assert_eq!(factory.predict_escrow(test_params()), predicted);
+
+            // Fill index, order hash, constructor input and code hash each change the address
+            let mut params = test_params();
+            params.fill_index = Some(0);
+            assert_ne!(factory.predict_escrow(params), predicted);
+            let mut params = test_params();
+            params.order_hash = Hash::from([8u8; 32]);
+            assert_ne!(factory.predict_escrow(params), predicted);
+            let mut params = test_params();
+            params.parts_count = 5;
+            assert_ne!(factory.predict_escrow(params), predicted);
+            let other_factory = EscrowFactory::new(Hash::from([8u8; 32]), 1000);
+            assert_ne!(other_factory.predict_escrow(test_params()), predicted);
         }
     }
 
     /// E2E tests
-    #[cfg(all(test, feature = "e2e-tests"))]
+    #[cfg(all(test, feature = "e2e-tests", not(feature = "pallet-assets")))]
     mod e2e_tests {
         use super::*;
         use ink_e2e::ContractsBackend;
@@ -129,7 +1161,7 @@ mod escrow_factory {
         async fn test_deploy_escrow(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
             // Given
             let code_hash = Hash::from([1u8; 32]);
-            let mut constructor = EscrowFactoryRef::new(code_hash);
+            let mut constructor = EscrowFactoryRef::new(code_hash, 1000);
 
             // When
             let contract = client
@@ -146,5 +1178,130 @@ mod escrow_factory {
 
             Ok(())
         }
+
+        #[ink_e2e::test]
+        async fn test_deploy_escrow_instantiates_dst(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let code_hash = client
+                .upload("escrow_dst", &ink_e2e::alice())
+                .submit()
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let mut constructor = EscrowFactoryRef::new(code_hash, 1000);
+            let contract = client
+                .instantiate("escrow_factory", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut call_builder = contract.call_builder::<EscrowFactory>();
+
+            // Alice makes the order with her ECDSA key, see `MakerSignature::Ecdsa`
+            let alice = ink_e2e::subxt_signer::ecdsa::dev::alice();
+            let mut maker = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(&alice.public_key().0, &mut maker);
+
+            let merkle_root = Hash::from([5u8; 32]);
+            let order = Order {
+                maker: AccountId::from(maker),
+                taker: ink_e2e::account_id(ink_e2e::AccountKeyring::Bob),
+                merkle_root,
+                parts_count: 4,
+                src_chain_id: 1,
+                dst_chain_id: 1000,
+                making_amount: 1_000_000_000,
+                taking_amount: 1_000_000_000,
+                token: None,
+                asset_id: None,
+                timelocks: escrow_dst::Timelocks::new(0, u32::MAX, u32::MAX, u32::MAX),
+                time_unit: escrow_dst::TimeUnit::Milliseconds,
+                safety_deposit: 0,
+                proof_mode: escrow_dst::ProofMode::SortedPairs,
+                hash_algorithm: escrow_dst::HashAlgorithm::Keccak256,
+                bind_leaves: false,
+                nonce: 0,
+            };
+            let get_order_hash = call_builder.get_order_hash(order.clone(), OrderEncoding::Scale);
+            let order_hash = client
+                .call(&ink_e2e::alice(), &get_order_hash)
+                .dry_run()
+                .await?
+                .return_value();
+            let params = EscrowParams {
+                maker: order.maker,
+                taker: order.taker,
+                merkle_root,
+                parts_count: 4,
+                timelocks: order.timelocks,
+                time_unit: order.time_unit,
+                token: None,
+                asset_id: None,
+                amount: 1_000_000_000,
+                safety_deposit: 0,
+                proof_mode: order.proof_mode,
+                hash_algorithm: order.hash_algorithm,
+                leaf_domain: None,
+                order_hash,
+                fill_index: None,
+            };
+            let funding = params.amount;
+
+            let predict = call_builder.predict_escrow(params.clone());
+            let predicted = client.call(&ink_e2e::alice(), &predict).dry_run().await?.return_value();
+
+            // Alice signs the order
+            let signature = MakerSignature::Ecdsa(alice.sign(order_hash.as_ref()).0);
+
+            // When
+            let deploy = call_builder.deploy_escrow(
+                order.clone(),
+                OrderEncoding::Scale,
+                params.clone(),
+                signature.clone(),
+            );
+            let result = client
+                .call(&ink_e2e::bob(), &deploy)
+                .value(funding)
+                .submit()
+                .await
+                .expect("deploy_escrow failed");
+            let escrow = result.return_value().expect("deployment rejected");
+            assert_eq!(escrow, predicted);
+
+            // Then the escrow is stored under the order hash
+            let get_deployed = call_builder.get_deployed_escrow(order_hash);
+            let stored = client.call(&ink_e2e::alice(), &get_deployed).dry_run().await?;
+            assert_eq!(stored.return_value(), Some(escrow));
+
+            let escrow_call_builder = ink_e2e::create_call_builder::<escrow_dst::EscrowDst>(escrow);
+            let get_merkle_root = escrow_call_builder.get_merkle_root();
+            let root = client.call(&ink_e2e::alice(), &get_merkle_root).dry_run().await?;
+            assert_eq!(root.return_value(), merkle_root);
+
+            let get_escrow_order_hash = escrow_call_builder.get_order_hash();
+            let stored_hash = client.call(&ink_e2e::alice(), &get_escrow_order_hash).dry_run().await?;
+            assert_eq!(stored_hash.return_value(), order_hash);
+
+            let by_maker = call_builder.escrows_by_maker(order.maker, 0, 10);
+            let by_maker = client.call(&ink_e2e::alice(), &by_maker).dry_run().await?;
+            assert_eq!(by_maker.return_value(), vec![escrow]);
+            let by_taker = call_builder.escrows_by_taker(order.taker, 0, 10);
+            let by_taker = client.call(&ink_e2e::alice(), &by_taker).dry_run().await?;
+            assert_eq!(by_taker.return_value(), vec![escrow]);
+            let get_escrow_count = call_builder.get_escrow_count();
+            let escrow_count = client.call(&ink_e2e::alice(), &get_escrow_count).dry_run().await?;
+            assert_eq!(escrow_count.return_value(), 1);
+
+            let get_funded_amount = escrow_call_builder.get_funded_amount();
+            let funded = client.call(&ink_e2e::alice(), &get_funded_amount).dry_run().await?;
+            assert_eq!(funded.return_value(), funding);
+
+            // Redeploying the same order is rejected
+            let redeploy = call_builder.deploy_escrow(order, OrderEncoding::Scale, params, signature);
+            let result = client.call(&ink_e2e::bob(), &redeploy).dry_run().await?;
+            assert_eq!(result.return_value(), Err(Error::EscrowAlreadyExists));
+
+            Ok(())
+        }
     }
 }
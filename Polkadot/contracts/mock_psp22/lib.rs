@@ -0,0 +1,196 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::mock_psp22::{MockPsp22, MockPsp22Ref, PSP22Error};
+
+/// Subset of the PSP22 standard used by EscrowDst
+///
+/// The trait is named `PSP22` so message selectors match the standard,
+/// e.g. `PSP22::transfer`.
+#[ink::trait_definition]
+pub trait PSP22 {
+    /// Get the balance of `owner`
+    #[ink(message)]
+    fn balance_of(&self, owner: ink::primitives::AccountId) -> u128;
+
+    /// Get the amount `spender` may transfer on behalf of `owner`
+    #[ink(message)]
+    fn allowance(&self, owner: ink::primitives::AccountId, spender: ink::primitives::AccountId) -> u128;
+
+    /// Transfer `value` from the caller to `to`
+    #[ink(message)]
+    fn transfer(
+        &mut self,
+        to: ink::primitives::AccountId,
+        value: u128,
+        data: ink::prelude::vec::Vec<u8>,
+    ) -> Result<(), PSP22Error>;
+
+    /// Transfer `value` from `from` to `to` using the caller's allowance
+    #[ink(message)]
+    fn transfer_from(
+        &mut self,
+        from: ink::primitives::AccountId,
+        to: ink::primitives::AccountId,
+        value: u128,
+        data: ink::prelude::vec::Vec<u8>,
+    ) -> Result<(), PSP22Error>;
+
+    /// Allow `spender` to transfer up to `value` on behalf of the caller
+    #[ink(message)]
+    fn approve(&mut self, spender: ink::primitives::AccountId, value: u128) -> Result<(), PSP22Error>;
+}
+
+/// Minimal PSP22 token for testing token escrows
+///
+/// Runs in the default environment, like the EscrowDst build the E2E tests drive.
+#[ink::contract]
+mod mock_psp22 {
+    use super::PSP22;
+    use ink::prelude::{string::String, vec::Vec};
+    use ink::storage::Mapping;
+
+    #[ink(storage)]
+    pub struct MockPsp22 {
+        /// Total token supply
+        total_supply: Balance,
+        /// Balance of each account
+        balances: Mapping<AccountId, Balance>,
+        /// Allowance of each (owner, spender) pair
+        allowances: Mapping<(AccountId, AccountId), Balance>,
+    }
+
+    /// Errors defined by the PSP22 standard
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum PSP22Error {
+        /// Custom error type for implementation-based errors
+        Custom(String),
+        /// Returned when an account does not have enough tokens
+        InsufficientBalance,
+        /// Returned when the allowance is not sufficient
+        InsufficientAllowance,
+        /// Returned when the recipient is the zero address
+        ZeroRecipientAddress,
+        /// Returned when the sender is the zero address
+        ZeroSenderAddress,
+        /// Returned when a safe transfer check fails
+        SafeTransferCheckFailed(String),
+    }
+
+    impl MockPsp22 {
+        /// Mint `total_supply` tokens to the caller
+        #[ink(constructor)]
+        pub fn new(total_supply: Balance) -> Self {
+            let mut balances = Mapping::default();
+            balances.insert(Self::env().caller(), &total_supply);
+            Self {
+                total_supply,
+                balances,
+                allowances: Mapping::default(),
+            }
+        }
+
+        /// Get the total token supply
+        #[ink(message)]
+        pub fn total_supply(&self) -> Balance {
+            self.total_supply
+        }
+
+        /// Move `value` between accounts
+        fn transfer_internal(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+        ) -> Result<(), PSP22Error> {
+            let from_balance = self.balance_of(from);
+            if from_balance < value {
+                return Err(PSP22Error::InsufficientBalance);
+            }
+            self.balances.insert(from, &(from_balance - value));
+            let to_balance = self.balance_of(to);
+            self.balances.insert(to, &to_balance.saturating_add(value));
+            Ok(())
+        }
+    }
+
+    impl PSP22 for MockPsp22 {
+        #[ink(message)]
+        fn balance_of(&self, owner: AccountId) -> Balance {
+            self.balances.get(owner).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            self.allowances.get((owner, spender)).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        fn transfer(&mut self, to: AccountId, value: Balance, _data: Vec<u8>) -> Result<(), PSP22Error> {
+            self.transfer_internal(self.env().caller(), to, value)
+        }
+
+        #[ink(message)]
+        fn transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+            _data: Vec<u8>,
+        ) -> Result<(), PSP22Error> {
+            let spender = self.env().caller();
+            let allowance = self.allowance(from, spender);
+            if allowance < value {
+                return Err(PSP22Error::InsufficientAllowance);
+            }
+            self.transfer_internal(from, to, value)?;
+            self.allowances.insert((from, spender), &(allowance - value));
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn approve(&mut self, spender: AccountId, value: Balance) -> Result<(), PSP22Error> {
+            self.allowances.insert((self.env().caller(), spender), &value);
+            Ok(())
+        }
+    }
+
+    /// Unit tests
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[ink::test]
+        fn test_transfer() {
+            let alice = AccountId::from([1u8; 32]);
+            let bob = AccountId::from([2u8; 32]);
+            let mut token = MockPsp22::new(100);
+
+            assert_eq!(token.transfer(bob, 30, Vec::new()), Ok(()));
+            assert_eq!(token.balance_of(alice), 70);
+            assert_eq!(token.balance_of(bob), 30);
+            assert_eq!(
+                token.transfer(bob, 71, Vec::new()),
+                Err(PSP22Error::InsufficientBalance)
+            );
+        }
+
+        #[ink::test]
+        fn test_transfer_from_uses_allowance() {
+            let alice = AccountId::from([1u8; 32]);
+            let bob = AccountId::from([2u8; 32]);
+            let charlie = AccountId::from([3u8; 32]);
+            let mut token = MockPsp22::new(100);
+            assert_eq!(token.approve(bob, 50), Ok(()));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(token.transfer_from(alice, charlie, 40, Vec::new()), Ok(()));
+            assert_eq!(token.balance_of(charlie), 40);
+            assert_eq!(token.allowance(alice, bob), 10);
+            assert_eq!(
+                token.transfer_from(alice, charlie, 11, Vec::new()),
+                Err(PSP22Error::InsufficientAllowance)
+            );
+        }
+    }
+}
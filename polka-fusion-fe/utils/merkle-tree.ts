@@ -13,6 +13,22 @@ export interface MerkleProof {
   root: string
 }
 
+/**
+ * Context binding a secrets tree to the escrows of one order (EscrowDst `LeafDomain`)
+ *
+ * Everything here is known before the tree is built; the order hash and escrow
+ * address are not, as both depend on the Merkle root.
+ */
+export interface LeafDomain {
+  chainId: bigint | number
+  // EscrowFactory contract address as 32 bytes hex
+  factory: string
+  // Maker account as 32 bytes hex
+  maker: string
+  // Nonce of the order
+  nonce: bigint | number
+}
+
 export class MerkleTree {
   private leaves: string[]
   private layers: string[][]
@@ -43,10 +59,17 @@ export class MerkleTree {
   }
 
   /**
-   * Hash a pair of values using Keccak-256
+   * Hash a pair of values using Keccak-256, smaller value first
+   * (OpenZeppelin MerkleProof / EscrowDst ProofMode::SortedPairs)
    */
   private hashPair(left: string, right: string): string {
-    return ethers.keccak256(ethers.concat([left, right]))
+    return MerkleTree.hashSortedPair(left, right)
+  }
+
+  static hashSortedPair(a: string, b: string): string {
+    return a.toLowerCase() < b.toLowerCase()
+      ? ethers.keccak256(ethers.concat([a, b]))
+      : ethers.keccak256(ethers.concat([b, a]))
   }
 
   /**
@@ -85,40 +108,58 @@ export class MerkleTree {
     let currentHash = leaf
     
     for (const proofElement of proof) {
-      // Determine if current hash is left or right child
-      const isLeft = currentHash < proofElement
-      
-      if (isLeft) {
-        currentHash = ethers.keccak256(ethers.concat([currentHash, proofElement]))
-      } else {
-        currentHash = ethers.keccak256(ethers.concat([proofElement, currentHash]))
-      }
+      currentHash = MerkleTree.hashSortedPair(currentHash, proofElement)
     }
     
     return currentHash === root
   }
 
   /**
-   * Create a leaf from part index and secret
+   * Create a leaf from part index and secret, matching
+   * keccak256(abi.encodePacked(partIndex, secret)) in EscrowSrc, or
+   * keccak256(abi.encodePacked(domainSeparator, partIndex, secret)) for a
+   * domain-separated EscrowDst
    */
-  static createLeaf(partIndex: number, secret: string): string {
-    return ethers.keccak256(
-      ethers.AbiCoder.defaultAbiCoder().encode(
-        ["uint32", "bytes32"],
-        [partIndex, secret]
+  static createLeaf(partIndex: number, secret: string | Uint8Array, domainSeparator?: string): string {
+    if (domainSeparator) {
+      return ethers.solidityPackedKeccak256(
+        ["bytes32", "uint32", "bytes32"],
+        [domainSeparator, partIndex, secret]
       )
+    }
+    return ethers.solidityPackedKeccak256(["uint32", "bytes32"], [partIndex, secret])
+  }
+
+  /**
+   * Domain separator of an order's escrow, matching EscrowDst `get_domain_separator`:
+   * keccak256(abi.encodePacked(uint64 chainId, bytes32 factory, bytes32 maker, uint64 nonce))
+   * for the whole-order escrow (and EscrowSrc), followed by uint32 fillIndex for the
+   * escrow of a partial fill
+   */
+  static domainSeparator(domain: LeafDomain, fillIndex?: number): string {
+    if (fillIndex !== undefined) {
+      return ethers.solidityPackedKeccak256(
+        ["uint64", "bytes32", "bytes32", "uint64", "uint32"],
+        [domain.chainId, domain.factory, domain.maker, domain.nonce, fillIndex]
+      )
+    }
+    return ethers.solidityPackedKeccak256(
+      ["uint64", "bytes32", "bytes32", "uint64"],
+      [domain.chainId, domain.factory, domain.maker, domain.nonce]
     )
   }
 }
 
 /**
- * Generate secrets and Merkle tree for escrow
+ * Generate secrets and Merkle tree for escrow, bound to a single order when a
+ * domain is given
  */
-export function generateEscrowMerkleTree(partsCount: number): {
+export function generateEscrowMerkleTree(partsCount: number, domain?: LeafDomain): {
   secrets: string[]
   merkleRoot: string
   proofs: string[][]
   leaves: string[]
+  domainSeparator?: string
 } {
   // Generate random secrets for each part
   const secrets = Array.from({ length: partsCount }, () => 
@@ -126,8 +167,9 @@ export function generateEscrowMerkleTree(partsCount: number): {
   )
 
   // Create leaves from secrets and part indices
+  const domainSeparator = domain ? MerkleTree.domainSeparator(domain) : undefined
   const leaves = secrets.map((secret, index) => 
-    MerkleTree.createLeaf(index, secret)
+    MerkleTree.createLeaf(index, secret, domainSeparator)
   )
 
   // Build Merkle tree
@@ -143,7 +185,39 @@ export function generateEscrowMerkleTree(partsCount: number): {
     secrets: secrets.map(secret => ethers.hexlify(secret)),
     merkleRoot,
     proofs,
-    leaves: leaves.map(leaf => ethers.hexlify(leaf))
+    leaves: leaves.map(leaf => ethers.hexlify(leaf)),
+    domainSeparator
+  }
+}
+
+/**
+ * Generate the N+1 secrets tree of an order taken in partial fills, leaf i bound
+ * to the escrow of fill i, so a secret revealed for one fill unlocks no other escrow
+ */
+export function generateFillMerkleTree(partsCount: number, domain: LeafDomain): {
+  secrets: string[]
+  merkleRoot: string
+  proofs: string[][]
+  leaves: string[]
+  domainSeparators: string[]
+} {
+  const secrets = Array.from({ length: partsCount + 1 }, () =>
+    ethers.randomBytes(32)
+  )
+  const domainSeparators = secrets.map((_, fillIndex) =>
+    MerkleTree.domainSeparator(domain, fillIndex)
+  )
+  const leaves = secrets.map((secret, index) =>
+    MerkleTree.createLeaf(index, secret, domainSeparators[index])
+  )
+
+  const merkleTree = new MerkleTree(leaves)
+  return {
+    secrets: secrets.map(secret => ethers.hexlify(secret)),
+    merkleRoot: merkleTree.getRoot(),
+    proofs: leaves.map((_, index) => merkleTree.getProof(index)),
+    leaves: leaves.map(leaf => ethers.hexlify(leaf)),
+    domainSeparators
   }
 }
 
@@ -154,9 +228,10 @@ export function verifyPartClaim(
   partIndex: number,
   secret: string,
   proof: string[],
-  merkleRoot: string
+  merkleRoot: string,
+  domainSeparator?: string
 ): boolean {
-  const leaf = MerkleTree.createLeaf(partIndex, secret)
+  const leaf = MerkleTree.createLeaf(partIndex, secret, domainSeparator)
   return MerkleTree.verify(proof, merkleRoot, leaf)
 }
 